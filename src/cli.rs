@@ -4,22 +4,118 @@
 // the terms of the Do What The Fuck You Want To Public License, Version 2, as
 // published by Sam Hocevar. See the COPYING file for more details.
 
-use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, Shell, SubCommand};
 use failure::{err_msg, Error};
-use record::Level;
+use record::{Level, FORMATS};
 use std::io::stdout;
+use timestamps::MODES;
 
 lazy_static! {
     static ref ABOUT: String = { format!("A 'adb logcat' wrapper and log processor. Your config directory is \"{}\".",
                                          ::config_dir().display()) };
 }
 
+/// Filter arguments of the main command that are shared with the expect subcommand
+fn filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("filterspec")
+            .long("filterspec")
+            .short("F")
+            .takes_value(true)
+            .multiple(true)
+            .help("Logcat filterspecs like \"ActivityManager:I MyApp:V *:S\" that set the minimum level per tag. \
+                   Defaults to the content of ANDROID_LOG_TAGS"),
+        Arg::with_name("fixed_strings")
+            .long("fixed-strings")
+            .takes_value(true)
+            .multiple(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["tag", "message"])
+            .help("Treat tag and/or message filters as plain strings instead of regular expressions. Applies to both if no kind is given"),
+        Arg::with_name("ignore_case")
+            .long("ignore-case")
+            .takes_value(true)
+            .multiple(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["tag", "message"])
+            .help("Match tag and/or message filters case insensitive. Applies to both if no kind is given"),
+        Arg::with_name("level")
+            .short("l")
+            .long("level")
+            .takes_value(true)
+            .possible_values(Level::values()).help("Minimum level"),
+        Arg::with_name("message")
+            .short("m")
+            .long("message")
+            .takes_value(true)
+            .multiple(true)
+            .help("Message filters in RE2. The prefix '!' inverts the match"),
+        Arg::with_name("package")
+            .long("package")
+            .takes_value(true)
+            .multiple(true)
+            .help("Package filters. Pids of running processes are queried on startup and new processes of this package are tracked"),
+        Arg::with_name("pid")
+            .long("pid")
+            .takes_value(true)
+            .multiple(true)
+            .help("Process id filters"),
+        Arg::with_name("since")
+            .long("since")
+            .takes_value(true)
+            .conflicts_with("tail")
+            .help("Skip records older than this. Pass a time like \"12:00\", \"03-01 12:00:01.123\" or \"2017-03-01 12:00\" \
+                   or a duration like \"1h30m\" that is subtracted from the current time. Passed to \"adb logcat -T\" when capturing"),
+        Arg::with_name("tag")
+            .short("t")
+            .long("tag")
+            .takes_value(true)
+            .multiple(true).help("Tag filters in RE2. The prefix '!' inverts the match"),
+        Arg::with_name("tid")
+            .long("tid")
+            .takes_value(true)
+            .multiple(true)
+            .help("Thread id filters"),
+        Arg::with_name("uid")
+            .long("uid")
+            .takes_value(true)
+            .multiple(true)
+            .help("User id filters. Works like the package filter for all packages with this uid"),
+        Arg::with_name("until")
+            .long("until")
+            .takes_value(true)
+            .help("Skip records newer than this. Same formats as --since. A duration is added to the --since time if given \
+                   or to the current time otherwise. Reading files stops once a record past this time is read if it has a date"),
+    ]
+}
+
 pub fn cli() -> App<'static, 'static> {
     App::new(crate_name!())
         .setting(AppSettings::ColoredHelp)
         .version(crate_version!())
         .author(crate_authors!())
         .about(ABOUT.as_str())
+        .group(ArgGroup::with_name("files")
+               .args(&["output", "flight_recorder"])
+               .multiple(true))
+        .args(&filter_args())
+        .arg(Arg::with_name("after_context")
+             .short("A")
+             .long("after-context")
+             .takes_value(true)
+             .help("Print n records following each match as context"))
+        .arg(Arg::with_name("append")
+             .long("append")
+             .requires("files")
+             .conflicts_with("overwrite")
+             .help("Append to existing output files. Not supported for HTML"))
+        .arg(Arg::with_name("before_context")
+             .short("B")
+             .long("before-context")
+             .takes_value(true)
+             .help("Print n records preceding each match as context"))
         .arg(Arg::with_name("buffer")
              .short("b")
              .long("buffer")
@@ -35,27 +131,95 @@ pub fn cli() -> App<'static, 'static> {
              .long("color")
              .takes_value(true)
              .possible_values(&["auto", "always", "never"])
-             .conflicts_with_all(&["highlight", "output"])
-             .help("Terminal coloring option"))
+             .help("Terminal coloring option. Files in the human format are colored with \"always\" only"))
+        .arg(Arg::with_name("compress")
+             .long("compress")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .possible_values(&["rotated", "stream"])
+             .requires("files")
+             .help("Gzip output files once they are complete (\"rotated\", default) or while they are written (\"stream\")"))
+        .arg(Arg::with_name("context")
+             .short("C")
+             .long("context")
+             .takes_value(true)
+             .help("Print n records before and after each match as context. Groups of records that are not adjacent are separated by \"--\""))
+        .arg(Arg::with_name("dedup")
+             .long("dedup")
+             .help("Collapse consecutive records with equal tag and message into one record and a summary"))
         .arg(Arg::with_name("dump")
              .short("d")
              .long("dump")
              .conflicts_with_all(&["input", "COMMAND", "restart"])
              .help("Dump the log and then exit (don't block)"))
+        .arg(Arg::with_name("dump_after")
+             .long("dump-after")
+             .takes_value(true)
+             .requires("flight_recorder")
+             .help("Number of records written after a flight recorder trigger. Defaults to 100"))
+        .arg(Arg::with_name("dump_file")
+             .long("dump-file")
+             .takes_value(true)
+             .requires("flight_recorder")
+             .help("Filename of flight recorder dumps. The filename format is date if not set otherwise. Defaults to rogcat-dump.log"))
+        .arg(Arg::with_name("dump_on")
+             .long("dump-on")
+             .takes_value(true)
+             .multiple(true)
+             .requires("flight_recorder")
+             .help("Dump the flight recorder if a message matches this regex or a record has at least this level. \
+                    Levels are full names (e.g. \"fatal\") or prefixed with \"level:\" (e.g. \"level:F\"). \
+                    On Unix SIGUSR1 triggers a dump as well"))
+        .arg(Arg::with_name("exit_on")
+             .long("exit-on")
+             .takes_value(true)
+             .multiple(true)
+             .help("Exit with 0 once a message matches this pattern in RE2. Exits with 3 if the run ends without a match"))
+        .arg(Arg::with_name("fail_late")
+             .long("fail-late")
+             .requires("fail_on")
+             .help("Continue if a --fail-on condition is met and exit with 2 when the run ends"))
+        .arg(Arg::with_name("fail_on")
+             .long("fail-on")
+             .takes_value(true)
+             .multiple(true)
+             .help("Exit with 2 once a message matches this pattern in RE2 or a record has at least this level. \
+                    Levels are full names (e.g. \"fatal\") or prefixed with \"level:\" (e.g. \"level:F\")"))
+        .arg(Arg::with_name("file_size")
+             .long("file-size")
+             .takes_value(true)
+             .requires("files")
+             .help("Start a new file once the current one reaches this size in bytes. Use k, M, G suffixes or a plain number"))
+        .arg(Arg::with_name("flight_recorder")
+             .long("flight-recorder")
+             .takes_value(true)
+             .help("Keep the last <COUNT> records or the records of the last duration e.g \"5m\" in memory and write them \
+                    to a new file if a --dump-on condition matches"))
         .arg(Arg::with_name("format")
              .long("format")
              .short("f")
              .takes_value(true)
-             .possible_values(&["csv", "html", "human", "json", "raw"]).help("Output format. Defaults to human on stdout and raw on file output"))
+             .possible_values(FORMATS)
+             .help("Output format. Defaults to human on stdout and raw on file output. \
+                    brief, long, process, tag, thread, threadtime and time are the formats of \"adb logcat -v\". \
+                    template renders each record with --template"))
         .arg(Arg::with_name("filename_format")
              .long("filename-format")
              .short("a")
              .takes_value(true)
-             .requires("output")
+             .requires("files")
              .possible_values(&["single", "enumerate", "date"])
              .help( "Select a format for output file names. By passing 'single' the filename provided with the '-o' option is used (default).\
                     'enumerate' appends a file sequence number after the filename passed with '-o' option whenever a new file is created \
-                    (see 'records-per-file' option). 'date' will prefix the output filename with the current local date when a new file is created"))
+                    (see 'records-per-file', 'file-size' and 'rotate' options). 'date' will prefix the output filename with the current local date when a new file is created"))
+        .arg(Arg::with_name("fsync")
+             .long("fsync")
+             .takes_value(true)
+             .min_values(0)
+             .require_equals(true)
+             .requires("files")
+             .help("Sync output files to disk after every record or with the first record after an interval like \"5s\""))
         .arg(Arg::with_name("head")
              .short("H")
              .long("head")
@@ -67,43 +231,60 @@ pub fn cli() -> App<'static, 'static> {
              .long("highlight")
              .takes_value(true)
              .multiple(true)
-             .conflicts_with_all(&["output"])
-             .help( "Highlight messages that match this pattern in RE2. The prefix '!' inverts the match"))
+             .help( "Highlight matches of this pattern in RE2 in tags and messages. Prefix the pattern with a color and '=' \
+                     to choose the color e.g \"red=^Exception\" or \"#ff8800=timeout\""))
+        .arg(Arg::with_name("highlight_filter")
+             .long("highlight-filter")
+             .help("Highlight matches of the message and tag filters"))
         .arg(Arg::with_name("input")
              .short("i")
              .long("input")
              .takes_value(true)
              .multiple(true)
              .help( "Read from file instead of command. Use 'serial://COM0@115200,8N1 or similiar for reading a serial port"))
-        .arg(Arg::with_name("level")
-             .short("l")
-             .long("level")
+        .arg(Arg::with_name("keep_files")
+             .long("keep-files")
              .takes_value(true)
-             .possible_values(Level::values()).help("Minimum level"))
-        .arg(Arg::with_name("message")
-             .short("m")
-             .long("message")
+             .requires("files")
+             .help("Delete the oldest output files written by this run to keep at most this number of files"))
+        .arg(Arg::with_name("keep_size")
+             .long("keep-size")
              .takes_value(true)
-             .multiple(true)
-             .help("Message filters in RE2. The prefix '!' inverts the match"))
+             .requires("files")
+             .help("Delete the oldest output files written by this run once their total size exceeds this number of bytes. \
+                    Use k, M, G suffixes or a plain number"))
+        .arg(Arg::with_name("markers")
+             .long("markers")
+             .conflicts_with("tui")
+             .help("Insert a marker record whenever enter is pressed. Text typed before enter is the label of the marker. Markers are inserted on SIGUSR1 as well unless the flight recorder is enabled"))
         .arg(Arg::with_name("no_dimm")
              .long("no-dimm")
-             .conflicts_with("output")
              .help("Use white as dimm color"))
         .arg(Arg::with_name("hide_timestamp")
              .long("hide-timestamp")
-             .conflicts_with("output")
-             .help("Hide timestamp in terminal output"))
+             .help("Hide timestamp in human output"))
         .arg(Arg::with_name("output")
              .short("o")
              .long("output")
              .takes_value(true)
-             .conflicts_with("color")
              .help("Write output to file"))
         .arg(Arg::with_name("overwrite")
              .long("overwrite")
              .requires("output")
              .help("Overwrite output file if present"))
+        .arg(Arg::with_name("pager")
+             .long("pager")
+             .conflicts_with_all(&["output", "tui"])
+             .help("Pipe the output to $PAGER (or less) if stdout is a terminal"))
+        .arg(Arg::with_name("pretty")
+             .long("pretty")
+             .takes_value(true)
+             .multiple(true)
+             .min_values(0)
+             .require_equals(true)
+             .possible_values(&["json", "kv"])
+             .conflicts_with("output")
+             .help("Pretty print JSON and/or key=value messages in terminal output. Selects JSON if no kind is given"))
         .arg(Arg::with_name("profiles_path")
              .short("P")
              .long("profiles-path")
@@ -114,44 +295,82 @@ pub fn cli() -> App<'static, 'static> {
              .long("profile")
              .takes_value(true)
              .help("Select profile"))
+        .arg(Arg::with_name("rate_limit")
+             .long("rate-limit")
+             .takes_value(true)
+             .help("Maximum number of records per tag and second. Dropped records are summarized"))
         .arg(Arg::with_name("records_per_file")
              .short("n")
              .long("records-per-file")
              .takes_value(true)
-             .requires("output")
+             .requires("files")
              .help( "Write n records per file. Use k, M, G suffixes or a plain number"))
         .arg(Arg::with_name("restart")
              .short("r")
              .long("restart")
              .conflicts_with_all(&["dump", "input", "tail"])
              .help("Restart command on exit"))
+        .arg(Arg::with_name("rotate")
+             .long("rotate")
+             .takes_value(true)
+             .requires("files")
+             .help("Start a new file after a duration like \"30m\" or at the beginning of every hour (\"hourly\") or day (\"midnight\")"))
         .arg(Arg::with_name("skip")
              .short("s")
              .long("skip")
              .help("Skip records on a command restart until the last received last record is received again. Use with caution!"))
         .arg(Arg::with_name("shorten_tags")
              .long("shorten-tags")
-             .conflicts_with("output")
              .help( "Shorten tags by removing vovels if too long for human terminal format"))
         .arg(Arg::with_name("show_date")
              .long("show-date")
-             .conflicts_with("output")
-             .help("Show month and day in terminal output"))
+             .help("Show month and day in human output"))
         .arg(Arg::with_name("show_time_diff")
              .long("show-time-diff")
-             .conflicts_with("output")
-             .help( "Show the time difference between the occurence of equal tags in terminal output"))
-        .arg(Arg::with_name("tag")
-             .short("t")
-             .long("tag")
-             .takes_value(true)
-             .multiple(true).help("Tag filters in RE2. The prefix '!' inverts the match"))
+             .help( "Show the time difference between the occurence of equal tags in human output"))
         .arg(Arg::with_name("tail")
              .short("T")
              .long("tail")
              .takes_value(true)
              .conflicts_with_all(&["input", "COMMAND", "restart"])
              .help("Dump only the most recent <COUNT> lines (implies --dump)"))
+        .arg(Arg::with_name("template")
+             .long("template")
+             .takes_value(true)
+             .help("Handlebars template of the template format or @FILE to read it from a file. Records have the fields \
+                    timestamp, level, tag, process, thread, message, raw and context. Helpers: \
+                    {{color COLOR value}} where COLOR is a color or \"level\", \
+                    {{pad value WIDTH [align=left|right|center] [truncate=clip|clip-start|ellipsis|vowels]}}, \
+                    {{time timestamp FORMAT}} and {{json value}}"))
+        .arg(Arg::with_name("time")
+             .long("time")
+             .takes_value(true)
+             .possible_values(MODES)
+             .help("Timestamp display mode. \"relative\" and \"delta\" show the seconds since the first and previous record, \"local\" and \"utc\" convert the device time and \"epoch\" shows seconds since 1970"))
+        .arg(Arg::with_name("timeout")
+             .long("timeout")
+             .takes_value(true)
+             .help("End the run after this duration. Pass seconds or a duration like \"5m\""))
+        .arg(Arg::with_name("trigger")
+             .long("trigger")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(2)
+             .value_names(&["PATTERN", "COMMAND"])
+             .help("Run COMMAND if a message matches PATTERN. Record fields are passed as environment variables \
+                    ROGCAT_<FIELD> and can be used as template arguments e.g \"echo {{message}}\""))
+        .arg(Arg::with_name("trigger_interval")
+             .long("trigger-interval")
+             .takes_value(true)
+             .help("Minimum duration between two runs of a trigger command. Defaults to 10s"))
+        .arg(Arg::with_name("tui")
+             .long("tui")
+             .conflicts_with("output")
+             .help("Interactive pager with scrollback, search and filtering. Press q to quit"))
+        .arg(Arg::with_name("width")
+             .long("width")
+             .takes_value(true)
+             .help("Wrap human output at this number of columns instead of the terminal width. 0 disables wrapping. Files are not wrapped by default"))
         .arg(Arg::with_name("COMMAND")
              .help( "Optional command to run and capture stdout from. Pass \"-\" to d capture stdin'. If omitted, rogcat will run \"adb logcat -b all\" and restarts this commmand if 'adb' terminates",))
         .subcommand(SubCommand::with_name("bugreport")
@@ -167,6 +386,21 @@ pub fn cli() -> App<'static, 'static> {
                         .help("The shell to generate the script for")))
         .subcommand(SubCommand::with_name("devices")
                 .about("Show list of available devices"))
+        .subcommand(SubCommand::with_name("expect")
+                .about("Check the log against a script of expected and forbidden patterns")
+                .arg(Arg::with_name("input")
+                     .short("i")
+                     .long("input")
+                     .takes_value(true)
+                     .multiple(true)
+                     .help("Read from file instead of command"))
+                .args(&filter_args())
+                .arg(Arg::with_name("SCRIPT")
+                     .required(true)
+                     .help("Script with [[step]] patterns that must appear in order and [[never]] patterns that must not appear. \
+                            Patterns have optional message, tag, level and timeout settings"))
+                .arg(Arg::with_name("COMMAND")
+                     .help("Optional command to run and capture stdout from. Pass \"-\" to capture stdin")))
         .subcommand(SubCommand::with_name("profiles")
                 .about("Show and manage profiles")
                 .arg(Arg::with_name("list")
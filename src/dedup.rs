@@ -0,0 +1,251 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::Error;
+use profiles::Profile;
+use record::Record;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Per tag state of the rate limiter
+struct Window {
+    start: i64,
+    count: u32,
+    dropped: u32,
+    last: Option<Record>,
+}
+
+/// Collapses consecutive records with equal tag and message and limits
+/// the number of records per tag and second. Suppressed records are
+/// replaced by a summary record.
+pub struct Dedup {
+    dedup: bool,
+    last: Option<Record>,
+    rate_limit: Option<u32>,
+    repeated: u32,
+    repeated_last: Option<Record>,
+    started: Instant,
+    windows: HashMap<String, Window>,
+}
+
+impl<'a> Dedup {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile) -> Result<Self, Error> {
+        let rate_limit = if args.is_present("rate_limit") {
+            Some(value_t!(args, "rate_limit", u32)?)
+        } else {
+            *profile.rate_limit()
+        };
+        if rate_limit == Some(0) {
+            return Err(format_err!("Rate limit must be greater than 0"));
+        }
+        Ok(Dedup::with(
+            args.is_present("dedup") || profile.dedup(),
+            rate_limit,
+        ))
+    }
+
+    fn with(dedup: bool, rate_limit: Option<u32>) -> Dedup {
+        Dedup {
+            dedup,
+            last: None,
+            rate_limit,
+            repeated: 0,
+            repeated_last: None,
+            started: Instant::now(),
+            windows: HashMap::new(),
+        }
+    }
+
+    fn summary(record: Record, message: String) -> Record {
+        Record {
+            raw: message.clone(),
+            message,
+            context: record.context.map(|_| false),
+            ..record
+        }
+    }
+
+    /// Summary for the repetitions of the last record if any
+    fn flush_repeated(&mut self) -> Option<Record> {
+        let repeated = self.repeated;
+        self.repeated = 0;
+        self.repeated_last.take().map(|r| {
+            Self::summary(r, format!("Last message repeated {}", plural(repeated, "time")))
+        })
+    }
+
+    fn dropped_message(dropped: u32, limit: u32) -> String {
+        format!(
+            "Dropped {} exceeding {} records per second",
+            plural(dropped, "record"),
+            limit
+        )
+    }
+
+    /// Milliseconds used for the rate limiter. Records without
+    /// timestamps are limited by wall clock time.
+    fn millis(&self, record: &Record) -> i64 {
+        match record.timestamp {
+            Some(ref ts) => {
+                let t = ts.to_timespec();
+                t.sec * 1000 + i64::from(t.nsec / 1_000_000)
+            }
+            None => {
+                let e = self.started.elapsed();
+                e.as_secs() as i64 * 1000 + i64::from(e.subsec_nanos() / 1_000_000)
+            }
+        }
+    }
+
+    /// Removes the windows of all tags that are expired at `now` and pushes
+    /// the summaries of their dropped records to `result` in window order.
+    fn expire(&mut self, now: i64, limit: u32, result: &mut Vec<Option<Record>>) {
+        let (mut expired, active): (Vec<_>, Vec<_>) = self.windows
+            .drain()
+            .partition(|&(_, ref w)| now < w.start || now - w.start >= 1000);
+        self.windows = active.into_iter().collect();
+        expired.sort_by_key(|&(_, ref w)| w.start);
+        for (_, w) in expired {
+            if let Some(last) = w.last {
+                result.push(Some(Self::summary(
+                    last,
+                    Self::dropped_message(w.dropped, limit),
+                )));
+            }
+        }
+    }
+
+    /// Returns false and records the drop if the tag exceeds the rate limit.
+    /// Pending summaries of expired windows are pushed to `result`.
+    fn limit(&mut self, record: &Record, result: &mut Vec<Option<Record>>) -> bool {
+        let limit = match self.rate_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let now = self.millis(record);
+        self.expire(now, limit, result);
+        let window = self.windows.entry(record.tag.clone()).or_insert(Window {
+            start: now,
+            count: 0,
+            dropped: 0,
+            last: None,
+        });
+
+        window.count += 1;
+        if window.count > limit {
+            window.dropped += 1;
+            window.last = Some(record.clone());
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn process(&mut self, record: Option<Record>) -> Vec<Option<Record>> {
+        if !self.dedup && self.rate_limit.is_none() {
+            return vec![record];
+        }
+
+        let record = match record {
+            Some(r) => r,
+            None => {
+                // Flush all pending summaries
+                let mut result = vec![];
+                result.extend(self.flush_repeated().map(Some));
+                if let Some(limit) = self.rate_limit {
+                    self.expire(::std::i64::MIN, limit, &mut result);
+                }
+                result.push(None);
+                return result;
+            }
+        };
+
+        let mut result = vec![];
+
+        if self.dedup && !record.is_separator() {
+            let repeated = self.last
+                .as_ref()
+                .map(|l| l.tag == record.tag && l.message == record.message)
+                .unwrap_or(false);
+            if repeated {
+                self.repeated += 1;
+                self.repeated_last = Some(record);
+                return result;
+            }
+            result.extend(self.flush_repeated().map(Some));
+        }
+
+        if !self.limit(&record, &mut result) {
+            return result;
+        }
+
+        if self.dedup {
+            self.last = Some(record.clone());
+        }
+        result.push(Some(record));
+        result
+    }
+}
+
+fn plural(count: u32, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+#[cfg(test)]
+fn messages(dedup: &mut Dedup, records: &[(&str, &str)]) -> Vec<String> {
+    let mut records = records
+        .iter()
+        .map(|&(tag, message)| {
+            Some(Record {
+                tag: tag.to_owned(),
+                message: message.to_owned(),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<Option<Record>>>();
+    records.push(None);
+    records
+        .drain(..)
+        .flat_map(|r| dedup.process(r))
+        .filter_map(|r| r.map(|r| format!("{}: {}", r.tag, r.message)))
+        .collect()
+}
+
+#[test]
+fn dedup() {
+    let mut dedup = Dedup::with(true, None);
+    let records = [("A", "a"), ("A", "a"), ("A", "a"), ("B", "a"), ("B", "b"), ("B", "b")];
+    assert_eq!(
+        messages(&mut dedup, &records),
+        vec![
+            "A: a",
+            "A: Last message repeated 2 times",
+            "B: a",
+            "B: b",
+            "B: Last message repeated 1 time",
+        ]
+    );
+}
+
+#[test]
+fn rate_limit() {
+    let mut dedup = Dedup::with(false, Some(2));
+    let records = [("A", "1"), ("A", "2"), ("B", "1"), ("A", "3"), ("A", "4")];
+    assert_eq!(
+        messages(&mut dedup, &records),
+        vec![
+            "A: 1",
+            "A: 2",
+            "B: 1",
+            "A: Dropped 2 records exceeding 2 records per second",
+        ]
+    );
+}
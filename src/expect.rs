@@ -0,0 +1,394 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::{err_msg, Error};
+use filter::Filter;
+use futures::future::ok;
+use futures::stream::once;
+use futures::{Future, Stream};
+use outcome::{EXIT_FAILED, EXIT_NOT_FOUND};
+use parser::Parser;
+use profiles::Profile;
+use record::{Level, Record};
+use regex::Regex;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Core, Interval};
+use tokio_signal;
+use toml::from_str;
+use utils::parse_duration;
+
+/// Interval of timeout checks while no records arrive
+const TICK_MS: u64 = 100;
+
+#[derive(Debug, Default, Deserialize)]
+struct ScriptFile {
+    timeout: Option<String>,
+    #[serde(default)]
+    step: Vec<PatternFile>,
+    #[serde(default)]
+    never: Vec<PatternFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PatternFile {
+    level: Option<String>,
+    message: Option<String>,
+    tag: Option<String>,
+    timeout: Option<String>,
+}
+
+fn duration(d: &str) -> Result<Duration, Error> {
+    parse_duration(d)
+        .and_then(|d| d.to_std().ok())
+        .ok_or_else(|| format_err!("Invalid timeout: {}", d))
+}
+
+/// A step of the script or a pattern that must not appear
+struct Pattern {
+    description: String,
+    level: Level,
+    message: Option<Regex>,
+    tag: Option<Regex>,
+    timeout: Option<(Duration, String)>,
+}
+
+impl Pattern {
+    fn new(p: &PatternFile) -> Result<Pattern, Error> {
+        let regex = |r: &String| Regex::new(r).map_err(|_| format_err!("Invalid regex string: {}", r));
+        let mut description = vec![];
+        if let Some(ref tag) = p.tag {
+            description.push(format!("tag \"{}\"", tag));
+        }
+        if let Some(ref level) = p.level {
+            description.push(format!("level {}", level));
+        }
+        if let Some(ref message) = p.message {
+            description.push(format!("message \"{}\"", message));
+        }
+        if description.is_empty() {
+            return Err(err_msg("Expect patterns need at least a tag, level or message"));
+        }
+
+        Ok(Pattern {
+            description: description.join(" "),
+            level: p.level.as_ref().map(|l| Level::from(l.as_str())).unwrap_or_default(),
+            message: p.message.as_ref().map(regex).map_or(Ok(None), |m| m.map(Some))?,
+            tag: p.tag.as_ref().map(regex).map_or(Ok(None), |t| t.map(Some))?,
+            timeout: p.timeout
+                .as_ref()
+                .map(|t| duration(t).map(|d| (d, t.clone())))
+                .map_or(Ok(None), |t| t.map(Some))?,
+        })
+    }
+
+    fn is_match(&self, record: &Record) -> bool {
+        record.level >= self.level
+            && self.message
+                .as_ref()
+                .map(|m| m.is_match(&record.message))
+                .unwrap_or(true)
+            && self.tag
+                .as_ref()
+                .map(|t| t.is_match(&record.tag))
+                .unwrap_or(true)
+    }
+}
+
+/// Input of the script state machine
+enum Event {
+    Record(Option<Record>),
+    Tick,
+}
+
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// The input ended before all steps were seen
+    Ended,
+    /// A never pattern was seen
+    Never(usize, Record),
+    /// The step or script timeout passed
+    Timeout(String),
+}
+
+/// Tracks the progress through a expect script
+pub struct Expect {
+    failure: Option<Failure>,
+    finished: bool,
+    matched: Vec<Record>,
+    never: Vec<Pattern>,
+    started: Instant,
+    step_started: Instant,
+    steps: Vec<Pattern>,
+    timeout: Option<(Duration, String)>,
+}
+
+impl Expect {
+    fn new(script: &Path) -> Result<Expect, Error> {
+        let mut content = String::new();
+        File::open(script)
+            .map_err(|e| format_err!("Failed to open {}: {}", script.display(), e))?
+            .read_to_string(&mut content)?;
+        let script: ScriptFile = from_str(&content)
+            .map_err(|e| format_err!("Failed to parse {}: {}", script.display(), e))?;
+        Expect::with(&script, Instant::now())
+    }
+
+    fn with(script: &ScriptFile, now: Instant) -> Result<Expect, Error> {
+        Ok(Expect {
+            failure: None,
+            finished: false,
+            matched: vec![],
+            never: script
+                .never
+                .iter()
+                .map(Pattern::new)
+                .collect::<Result<Vec<Pattern>, Error>>()?,
+            started: now,
+            step_started: now,
+            steps: script
+                .step
+                .iter()
+                .map(Pattern::new)
+                .collect::<Result<Vec<Pattern>, Error>>()?,
+            timeout: script
+                .timeout
+                .as_ref()
+                .map(|t| duration(t).map(|d| (d, t.clone())))
+                .map_or(Ok(None), |t| t.map(Some))?,
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        self.failure.is_some() || self.finished
+    }
+
+    fn remaining(&self) -> bool {
+        self.matched.len() < self.steps.len()
+    }
+
+    /// Process `event` and return false once the result is decided
+    fn process(&mut self, event: &Event, now: Instant) -> bool {
+        match *event {
+            Event::Record(Some(ref record)) => {
+                if let Some(n) = self.never.iter().position(|p| p.is_match(record)) {
+                    self.failure = Some(Failure::Never(n, record.clone()));
+                } else if self.remaining() && self.steps[self.matched.len()].is_match(record) {
+                    self.matched.push(record.clone());
+                    self.step_started = now;
+                }
+            }
+            Event::Record(None) => {
+                if self.remaining() {
+                    self.failure = Some(Failure::Ended);
+                }
+                self.finished = true;
+            }
+            Event::Tick => (),
+        }
+
+        if !self.is_done() {
+            let passed = |t: &(Duration, String), since: Instant| now.duration_since(since) >= t.0;
+            let step_timeout = self.steps
+                .get(self.matched.len())
+                .and_then(|s| s.timeout.as_ref())
+                .and_then(|t| if passed(t, self.step_started) { Some(t) } else { None });
+            let timeout = self.timeout
+                .as_ref()
+                .and_then(|t| if passed(t, self.started) { Some(t) } else { None });
+            if let Some(t) = step_timeout.or(timeout) {
+                if self.remaining() {
+                    self.failure = Some(Failure::Timeout(t.1.clone()));
+                }
+                self.finished = true;
+            }
+        }
+
+        !self.is_done()
+    }
+
+    fn report(&self) -> Vec<String> {
+        let mut report = vec![];
+        let total = self.steps.len();
+        for (n, step) in self.steps.iter().enumerate() {
+            let result = match self.matched.get(n) {
+                Some(r) => format!("PASS: {}", r.raw),
+                None if n == self.matched.len() => match self.failure {
+                    Some(Failure::Timeout(ref t)) => format!("FAIL: timeout after {}", t),
+                    Some(Failure::Ended) => "FAIL: not found".to_owned(),
+                    _ => "FAIL: not reached".to_owned(),
+                },
+                None => "FAIL: not reached".to_owned(),
+            };
+            report.push(format!("Step {}/{} {}: {}", n + 1, total, step.description, result));
+        }
+        for (n, never) in self.never.iter().enumerate() {
+            let result = match self.failure {
+                Some(Failure::Never(i, ref r)) if i == n => format!("FAIL: {}", r.raw),
+                _ => "PASS".to_owned(),
+            };
+            report.push(format!("Never {}: {}", never.description, result));
+        }
+        report.push(
+            if self.failure.is_none() {
+                "Result: PASS"
+            } else {
+                "Result: FAIL"
+            }.to_owned(),
+        );
+        report
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self.failure {
+            None => 0,
+            Some(Failure::Ended) => EXIT_NOT_FOUND,
+            Some(_) => EXIT_FAILED,
+        }
+    }
+}
+
+/// Run the input pipeline against a expect script and print a report.
+/// The never patterns are checked until the input ends or the script
+/// times out even if all steps passed.
+pub fn run(args: &ArgMatches, profile: &Profile, core: &mut Core) -> Result<i32, Error> {
+    let script = args.value_of("SCRIPT")
+        .ok_or_else(|| err_msg("Missing expect script"))?;
+    let mut expect = Expect::new(Path::new(script))?;
+    let mut parser = Parser::new();
+    let mut filter = Filter::new(args, profile)?;
+
+    // Files are expected to be sorted: stop reading once the window is passed
+    let window = filter.window().clone();
+    let file_input = args.values_of("input")
+        .map(|mut i| i.all(|i| !i.starts_with("serial://")))
+        .unwrap_or(false);
+
+    let input = ::input(core, args)?;
+    let ctrl_c = tokio_signal::ctrl_c(&core.handle())
+        .flatten_stream()
+        .map(|_| Event::Record(None))
+        .map_err(|e| e.into());
+    let ticks = Interval::new(Duration::from_millis(TICK_MS), &core.handle())?
+        .map(|_| Event::Tick)
+        .map_err(|e| e.into());
+
+    let result = input
+        .take_while(|i| ok(i.is_some()))
+        .chain(once(Ok(None)))
+        .and_then(|m| parser.process(m))
+        .map(|m| if file_input && window.is_passed(&m) { None } else { m })
+        .filter(|m| filter.filter(m))
+        .map(Event::Record)
+        .select(ctrl_c)
+        .select(ticks)
+        .take_while(|e| ok(expect.process(e, Instant::now())))
+        .for_each(|_| Ok(()));
+    core.run(result)?;
+
+    for line in expect.report() {
+        println!("{}", line);
+    }
+    Ok(expect.exit_code())
+}
+
+#[cfg(test)]
+fn script(steps: &[(&str, Option<&str>)], never: &[&str]) -> ScriptFile {
+    let pattern = |message: &str| PatternFile {
+        message: Some(message.to_owned()),
+        ..Default::default()
+    };
+    ScriptFile {
+        timeout: None,
+        step: steps
+            .iter()
+            .map(|&(m, t)| PatternFile {
+                timeout: t.map(|t| t.to_owned()),
+                ..pattern(m)
+            })
+            .collect(),
+        never: never.iter().map(|m| pattern(m)).collect(),
+    }
+}
+
+#[cfg(test)]
+fn record(message: &str) -> Event {
+    Event::Record(Some(Record {
+        message: message.to_owned(),
+        raw: message.to_owned(),
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn expect_steps() {
+    let now = Instant::now();
+    let mut expect = Expect::with(&script(&[("^A", None), ("^B", None)], &[]), now).unwrap();
+    assert!(expect.process(&record("B"), now));
+    assert!(expect.process(&record("A"), now));
+    assert!(expect.process(&record("A"), now));
+    assert!(expect.process(&record("B"), now));
+    assert!(!expect.process(&Event::Record(None), now));
+    assert_eq!(expect.exit_code(), 0);
+    assert_eq!(
+        expect.report(),
+        vec![
+            "Step 1/2 message \"^A\": PASS: A",
+            "Step 2/2 message \"^B\": PASS: B",
+            "Result: PASS",
+        ]
+    );
+
+    let mut expect = Expect::with(&script(&[("^A", None), ("^B", None)], &[]), now).unwrap();
+    assert!(expect.process(&record("A"), now));
+    assert!(!expect.process(&Event::Record(None), now));
+    assert_eq!(expect.exit_code(), EXIT_NOT_FOUND);
+    assert_eq!(expect.report()[1], "Step 2/2 message \"^B\": FAIL: not found");
+}
+
+#[test]
+fn expect_never() {
+    let now = Instant::now();
+    let mut expect = Expect::with(&script(&[("^A", None)], &["FATAL"]), now).unwrap();
+    assert!(!expect.process(&record("FATAL EXCEPTION"), now));
+    assert_eq!(expect.exit_code(), EXIT_FAILED);
+    assert_eq!(
+        expect.report(),
+        vec![
+            "Step 1/1 message \"^A\": FAIL: not reached",
+            "Never message \"FATAL\": FAIL: FATAL EXCEPTION",
+            "Result: FAIL",
+        ]
+    );
+
+    // Without steps the never patterns are checked until the input ends
+    let mut expect = Expect::with(&script(&[], &["FATAL"]), now).unwrap();
+    assert!(expect.process(&record("A"), now));
+    assert!(!expect.process(&Event::Record(None), now));
+    assert_eq!(expect.exit_code(), 0);
+
+    // Never patterns are checked after all steps passed
+    let mut expect = Expect::with(&script(&[("^A", None)], &["FATAL"]), now).unwrap();
+    assert!(expect.process(&record("A"), now));
+    assert!(!expect.process(&record("FATAL EXCEPTION"), now));
+    assert_eq!(expect.exit_code(), EXIT_FAILED);
+    assert_eq!(expect.report()[0], "Step 1/1 message \"^A\": PASS: A");
+}
+
+#[test]
+fn expect_timeout() {
+    let now = Instant::now();
+    let mut expect = Expect::with(&script(&[("^A", None), ("^B", Some("5s"))], &[]), now).unwrap();
+    assert!(expect.process(&Event::Tick, now + Duration::from_secs(60)));
+    assert!(expect.process(&record("A"), now + Duration::from_secs(60)));
+    assert!(expect.process(&Event::Tick, now + Duration::from_secs(64)));
+    assert!(!expect.process(&Event::Tick, now + Duration::from_secs(65)));
+    assert_eq!(expect.exit_code(), EXIT_FAILED);
+    assert_eq!(expect.report()[1], "Step 2/2 message \"^B\": FAIL: timeout after 5s");
+}
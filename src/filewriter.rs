@@ -7,36 +7,146 @@
 use clap::ArgMatches;
 use crc::{Hasher32, crc32};
 use failure::{err_msg, Error};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::{Async, AsyncSink, Poll, Sink, StartSend};
-use handlebars::{to_json, Handlebars, Helper, JsonRender, RenderContext, RenderError};
+use handlebars::{html_escape, to_json, Handlebars, Helper, JsonRender, RenderContext, RenderError};
+use highlight::{runs, Highlights};
 use indicatif::{ProgressBar, ProgressStyle};
-use regex::Regex;
+use profiles::Profile;
 use serde_json::value::{Map, Value as Json};
-use std::fs::{DirBuilder, File};
-use std::io::Write;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
 use std::str;
-use record::{Format, Record};
-use time::{now, strftime};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use record::{Format, Record, TIMESTAMP_FORMAT};
+use regex::Regex;
+use term::color::YELLOW;
+use terminal::Terminal;
+use theme::Color;
+use utils::{parse_duration, parse_size};
+use time::{now, strftime, Duration, Tm};
+use timestamps::{Mode, Timestamps};
 
 /// Interface for a output file format
 trait Writer {
-    fn new(filename: &PathBuf, format: &Format) -> Result<Box<Self>, Error>
+    fn new(filename: &PathBuf, format: &Format, append: bool) -> Result<Box<Self>, Error>
     where
         Self: Sized;
-    fn write(&mut self, record: &Record, index: usize) -> Result<(), Error>;
-    fn flush(&mut self) -> Result<(), Error> {
-        Ok(())
+    /// Write `record`. `timestamp` replaces the record timestamp if set.
+    fn write(&mut self, record: &Record, timestamp: Option<&str>, index: usize) -> Result<(), Error>;
+    /// Complete the file and write it to disk if `sync` is set
+    fn flush(&mut self, sync: bool) -> Result<(), Error>;
+    /// Write everything written so far to disk
+    fn sync(&mut self) -> Result<(), Error>;
+    /// Number of bytes written so far
+    fn size(&self) -> u64;
+}
+
+/// Output file. Files with a "gz" extension are gzipped while written.
+enum Output {
+    File(File),
+    Gzip(Box<GzEncoder<File>>),
+}
+
+impl Output {
+    /// Create `filename` or open it for appending. Appending to a gzipped
+    /// file adds a new gzip member.
+    fn create(filename: &Path, append: bool) -> Result<Output, Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(filename)
+            .map_err(|e| format_err!("Failed to create output file {}: {}", filename.display(), e))?;
+        if is_gz(filename) {
+            Ok(Output::Gzip(Box::new(GzEncoder::new(file, Compression::Default))))
+        } else {
+            Ok(Output::File(file))
+        }
+    }
+
+    fn file(&self) -> &File {
+        match *self {
+            Output::File(ref file) => file,
+            Output::Gzip(ref gzip) => gzip.get_ref(),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.flush()
+            .and_then(|_| self.file().sync_data())
+            .map_err(|e| format_err!("Failed to sync: {}", e))
     }
+
+    /// Write the gzip trailer and sync if `sync` is set. Nothing must be
+    /// written afterwards.
+    fn finish(&mut self, sync: bool) -> Result<(), Error> {
+        match *self {
+            Output::File(ref mut file) => file.flush(),
+            Output::Gzip(ref mut gzip) => gzip.try_finish(),
+        }.and_then(|_| if sync { self.file().sync_data() } else { Ok(()) })
+            .map_err(|e| format_err!("Failed to write: {}", e))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Output::File(ref mut file) => file.write(buf),
+            Output::Gzip(ref mut gzip) => gzip.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Output::File(ref mut file) => file.flush(),
+            Output::Gzip(ref mut gzip) => gzip.flush(),
+        }
+    }
+}
+
+fn is_gz(filename: &Path) -> bool {
+    filename.extension().map(|e| e == "gz").unwrap_or(false)
+}
+
+/// `filename` with a appended ".gz"
+fn gz(filename: &Path) -> PathBuf {
+    let mut name = filename.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
 }
 
-const TEMPLATE: &str = r#"
-<!doctype HTML>
+/// Compress `filename` and remove the uncompressed file. The compressed
+/// file is synced before the uncompressed one is removed.
+fn gzip(filename: &Path, append: bool) -> Result<PathBuf, Error> {
+    let target = gz(filename);
+    let mut input = File::open(filename)?;
+    let mut output = Output::create(&target, append)?;
+    io::copy(&mut input, &mut output)
+        .map_err(|e| format_err!("Failed to compress {}: {}", filename.display(), e))?;
+    output.finish(true)?;
+    fs::remove_file(filename)?;
+    Ok(target)
+}
+
+/// Start of a html page up to the table that records are streamed into.
+/// Styles and scripts are embedded to view pages offline.
+const HEADER: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
 <title>Rogcat</title>
-<link href='http://fonts.googleapis.com/css?family=Source+Code+Pro' rel='stylesheet' type='text/css'>
 <style>
-body {background: black; color: #BBBBBB; font-family: 'Source Code Pro', Monaco, monospace; font-size: 12px}
+body {background: black; color: #BBBBBB; font-family: 'Source Code Pro', Monaco, Menlo, Consolas, monospace; font-size: 12px; margin: 0}
+a {color: #96CBFE}
 .green, .I {color: #A8FF60}
 .white {color: #EEEEEE}
 .red, .E, .A, .F {color: #FF6C60}
@@ -45,7 +155,23 @@ body {background: black; color: #BBBBBB; font-family: 'Source Code Pro', Monaco,
 .blue {color: #96CBFE}
 .cyan {color: #C6C5FE}
 .magenta {color: #FF73FD}
-tr.hover { background: #260041 }
+#controls {
+    position: sticky;
+    top: 0;
+    padding: 1ex 2ex;
+    background: #1C1C1C;
+    border-bottom: 1px solid #4F4F4F;
+}
+#controls label {
+    margin-right: 1ex;
+}
+#controls input[type=text] {
+    background: black;
+    color: #EEEEEE;
+    border: 1px solid #4F4F4F;
+    font-family: inherit;
+    margin-right: 2ex;
+}
 table {
     border-spacing: 0;
     width: 100%;
@@ -60,6 +186,9 @@ td {
 tr:hover {
     color: yellow;
 }
+tr.filtered, tr.collapsed {
+    display: none;
+}
 td.level-D {
     color: white;
     background: #555;
@@ -72,58 +201,168 @@ td.level-W {
     color: black;
     background: #FFFFB6;
 }
-td.level-E {
-    color: black;
-    background: #FF6C60;
-}
-td.level-A {
-    color: black;
-    background: #FF6C60;
-}
-td.level-F {
+td.level-E, td.level-A, td.level-F {
     color: black;
     background: #FF6C60;
 }
 table tr td:first-child + td + td {
     text-align: right
 }
-table tr td:first-child + td + td + td {
-}
 table tr td:first-child + td + td + td + td {
     text-align: right
 }
-table tr td:first-child + td + td + td + td + td {
+table.hide-1 td:nth-child(1), table.hide-2 td:nth-child(2), table.hide-3 td:nth-child(3),
+table.hide-4 td:nth-child(4), table.hide-5 td:nth-child(5), table.hide-6 td:nth-child(6) {
+    display: none;
+}
+span.toggle {
+    cursor: pointer;
+    color: #96CBFE;
 }
 </style>
+<script>
+document.addEventListener('DOMContentLoaded', function () {
+    var table = document.getElementById('records');
+    var inputs = document.querySelectorAll('#controls input');
 
-<table>
-
-{{#each records as |t| ~}}
-    <tr>
-    <td>{{t.index}}</td>
-    <td>{{t.record.timestamp}}</td>
-    <td><a>{{color t.record.tag}}</a></td>
-    <td>{{color t.record.process}}</td>
-    <td>{{color t.record.thread}}</td>
-    <td class="level-{{t.record.level}}">{{t.record.level}}</td>
-    <td>{{t.record.message}}</td>
-    </tr>
-{{/each~}}
+    function regex(value) {
+        try {
+            return value ? new RegExp(value, 'i') : null;
+        } catch (e) {
+            return null;
+        }
+    }
+
+    function filter() {
+        var levels = {};
+        document.querySelectorAll('#controls input.level').forEach(function (input) {
+            levels[input.value] = input.checked;
+        });
+        var tag = regex(document.getElementById('tag').value);
+        var text = regex(document.getElementById('text').value);
+        Array.prototype.forEach.call(table.rows, function (row) {
+            var message = row.cells[row.cells.length - 1].textContent;
+            var visible = levels[row.getAttribute('data-level')] !== false &&
+                (!tag || tag.test(row.getAttribute('data-tag'))) &&
+                (!text || text.test(message));
+            row.classList.toggle('filtered', !visible);
+        });
+    }
 
-</table>
+    function columns() {
+        document.querySelectorAll('#controls input.column').forEach(function (input) {
+            table.classList.toggle('hide-' + input.value, !input.checked);
+        });
+    }
+
+    // Stack frames are collapsed into the preceding record
+    var owner = null;
+    Array.prototype.forEach.call(table.rows, function (row) {
+        if (!row.classList.contains('frame')) {
+            owner = row;
+            return;
+        }
+        if (!owner) {
+            return;
+        }
+        if (!owner.frames) {
+            var frames = owner.frames = [];
+            var toggle = document.createElement('span');
+            toggle.className = 'toggle';
+            toggle.textContent = '▸ ';
+            toggle.addEventListener('click', function () {
+                var collapse = !frames[0].classList.contains('collapsed');
+                frames.forEach(function (frame) {
+                    frame.classList.toggle('collapsed', collapse);
+                });
+                toggle.textContent = collapse ? '▸ ' : '▾ ';
+            });
+            var cell = owner.cells[owner.cells.length - 1];
+            cell.insertBefore(toggle, cell.firstChild);
+        }
+        owner.frames.push(row);
+        row.classList.add('collapsed');
+    });
+
+    Array.prototype.forEach.call(inputs, function (input) {
+        input.addEventListener(input.type === 'text' ? 'input' : 'change', function () {
+            filter();
+            columns();
+        });
+    });
+});
+</script>
+</head>
+<body>
+<div id="controls">
+<label><input type="checkbox" class="level" value="V" checked>V</label>
+<label><input type="checkbox" class="level" value="D" checked>D</label>
+<label><input type="checkbox" class="level" value="I" checked>I</label>
+<label><input type="checkbox" class="level" value="W" checked>W</label>
+<label><input type="checkbox" class="level" value="E" checked>E</label>
+<label><input type="checkbox" class="level" value="F" checked>F</label>
+<label><input type="checkbox" class="level" value="A" checked>A</label>
+&nbsp;
+Tag <input type="text" id="tag" placeholder="regex">
+Text <input type="text" id="text" placeholder="regex">
+<label><input type="checkbox" class="column" value="1" checked>Index</label>
+<label><input type="checkbox" class="column" value="2" checked>Time</label>
+<label><input type="checkbox" class="column" value="3" checked>Tag</label>
+<label><input type="checkbox" class="column" value="4" checked>Pid</label>
+<label><input type="checkbox" class="column" value="5" checked>Tid</label>
+<label><input type="checkbox" class="column" value="6" checked>Level</label>
+</div>
+<table id="records">
+"#;
+
+const ROW: &str = r#"<tr data-level="{{level}}" data-tag="{{record.tag}}"{{#if frame}} class="frame"{{/if}}><td>{{index}}</td><td>{{timestamp}}</td><td>{{color record.tag}}</td><td>{{color record.process}}</td><td>{{color record.thread}}</td><td class="level-{{level}}">{{level}}</td><td>{{{message}}}</td></tr>
+"#;
+
+const FOOTER: &str = "</table>\n</body>\n</html>\n";
+
+/// Links to the pages of a enumerated or dated rotation
+const INDEX: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Rogcat</title>
+<style>
+body {background: black; color: #BBBBBB; font-family: 'Source Code Pro', Monaco, Menlo, Consolas, monospace; font-size: 12px}
+a {color: #96CBFE}
+</style>
+</head>
+<body>
+<ul>
+{{#each files as |f| ~}}
+<li><a href="{{f}}">{{f}}</a></li>
+{{/each~}}
+</ul>
+</body>
+</html>
 "#;
 
+lazy_static! {
+    /// Messages of stack frames that are collapsed into the preceding record
+    static ref FRAME: Regex = Regex::new(r"^\s*(at [\w$.<>]+\(|\.\.\. \d+ more$)").unwrap();
+}
+
 #[derive(Serialize)]
 struct HtmlRecord {
+    frame: bool,
     index: usize,
+    level: String,
+    /// Escaped message with highlights
+    message: String,
     record: Record,
+    timestamp: Option<String>,
 }
 
-/// Simple static html file
-#[derive(Default)]
+/// Html page that records are streamed into
 struct Html {
-    filename: PathBuf,
-    records: Vec<HtmlRecord>,
+    file: Output,
+    handlebars: Handlebars,
+    highlights: Highlights,
+    size: u64,
 }
 
 impl Html {
@@ -152,77 +391,362 @@ impl Html {
             format!(
                 "<span style=\"color:{}\">{}</span>",
                 Self::hash_color(&value),
-                value
+                html_escape(&value)
             )
         };
         rc.writer.write_all(rendered.into_bytes().as_ref())?;
         Ok(())
     }
 
-    fn flush(&mut self) -> Result<(), Error> {
-        let mut hb = Handlebars::new();
-        let mut data: Map<String, Json> = Map::new();
-        data.insert("records".to_owned(), to_json(&self.records));
-        let mut output_file = File::create(&self.filename)?;
-        hb.register_helper("color", Box::new(Self::color_helper));
-        hb.register_template_string("t1", TEMPLATE)?;
-        hb.renderw("t1", &data, &mut output_file)
-            .map_err(|e| format_err!("Rednering error: {}", e))
-            .map(|_| ())
+    fn message(&self, message: &str) -> String {
+        let colors = self.highlights.message(message);
+        runs(message, &colors)
+            .iter()
+            .map(|&(run, color)| match color {
+                Some(c) => format!(
+                    "<span style=\"color:black;background:{}\">{}</span>",
+                    c.css(),
+                    html_escape(run)
+                ),
+                None => html_escape(run),
+            })
+            .collect()
+    }
+
+    fn write_all(&mut self, s: &str) -> Result<(), Error> {
+        self.file
+            .write_all(s.as_bytes())
+            .map_err(|e| format_err!("Failed to write: {}", e))?;
+        self.size += s.len() as u64;
+        Ok(())
     }
 }
 
 impl Writer for Html {
-    fn new(filename: &PathBuf, _: &Format) -> Result<Box<Self>, Error> {
-        let html = Html {
-            filename: filename.clone(),
-            records: Vec::new(),
+    fn new(filename: &PathBuf, _: &Format, _append: bool) -> Result<Box<Self>, Error> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("color", Box::new(Self::color_helper));
+        handlebars.register_template_string("row", ROW)?;
+        let mut html = Html {
+            file: Output::create(filename, false)?,
+            handlebars,
+            highlights: Highlights::default(),
+            size: 0,
         };
+        html.write_all(HEADER)?;
         Ok(Box::new(html))
     }
 
-    fn write(&mut self, record: &Record, index: usize) -> Result<(), Error> {
+    fn write(&mut self, record: &Record, timestamp: Option<&str>, index: usize) -> Result<(), Error> {
+        let timestamp = timestamp.map(|t| t.to_owned()).or_else(|| {
+            record
+                .timestamp
+                .as_ref()
+                .and_then(|t| strftime(TIMESTAMP_FORMAT, t).ok())
+        });
         let r = HtmlRecord {
+            frame: FRAME.is_match(&record.message),
             index: index,
+            level: record.level.to_string(),
+            message: self.message(&record.message),
             record: record.clone(),
+            timestamp,
         };
-        Ok(self.records.push(r))
+        let row = self.handlebars
+            .render("row", &r)
+            .map_err(|e| format_err!("Rendering error: {}", e))?;
+        self.write_all(&row)
     }
-}
 
-impl Drop for Html {
-    fn drop(&mut self) {
-        self.flush().ok();
+    fn flush(&mut self, sync: bool) -> Result<(), Error> {
+        self.write_all(FOOTER)?;
+        self.file.finish(sync)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync()
+    }
+
+    fn size(&self) -> u64 {
+        self.size
     }
 }
 
 /// Textfile with format
 struct Textfile {
-    file: File,
+    file: Output,
     format: Format,
+    size: u64,
 }
 
 impl Writer for Textfile {
-    fn new(filename: &PathBuf, format: &Format) -> Result<Box<Self>, Error> {
-        let file = File::create(filename).map_err(|e| {
-            format_err!("Failed to create output file {}: {}", filename.display(), e)
-        })?;
+    fn new(filename: &PathBuf, format: &Format, append: bool) -> Result<Box<Self>, Error> {
+        let file = Output::create(filename, append)?;
         let textfile = Textfile {
             file: file,
             format: format.clone(),
+            size: 0,
         };
         Ok(Box::new(textfile))
     }
 
-    fn write(&mut self, record: &Record, _index: usize) -> Result<(), Error> {
+    fn write(&mut self, record: &Record, timestamp: Option<&str>, _index: usize) -> Result<(), Error> {
+        let line = match timestamp {
+            Some(timestamp) => record.format_with_timestamp(&self.format, timestamp)?,
+            None => record.format(&self.format)?,
+        };
         self.file
-            .write(record.format(&self.format)?.as_bytes())
+            .write(line.as_bytes())
             .map_err(|e| format_err!("Failed to write: {}", e))?;
         self.file
             .write(b"\n")
             .map_err(|e| format_err!("Failed to write: {}", e))?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn flush(&mut self, sync: bool) -> Result<(), Error> {
+        self.file.finish(sync)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync()
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Human format as printed on the terminal
+struct Humanfile {
+    file: Output,
+    renderer: Option<Rc<RefCell<Terminal<Vec<u8>>>>>,
+    size: u64,
+}
+
+impl Writer for Humanfile {
+    fn new(filename: &PathBuf, _format: &Format, append: bool) -> Result<Box<Self>, Error> {
+        let file = Output::create(filename, append)?;
+        Ok(Box::new(Humanfile {
+            file,
+            renderer: None,
+            size: 0,
+        }))
+    }
+
+    /// Timestamps are formatted by the renderer
+    fn write(&mut self, record: &Record, _timestamp: Option<&str>, _index: usize) -> Result<(), Error> {
+        let renderer = self.renderer
+            .as_ref()
+            .ok_or_else(|| err_msg("Missing human format renderer"))?;
+        for line in renderer.borrow_mut().render(record)? {
+            writeln!(self.file, "{}", line).map_err(|e| format_err!("Failed to write: {}", e))?;
+            self.size += line.len() as u64 + 1;
+        }
         Ok(())
     }
+
+    fn flush(&mut self, sync: bool) -> Result<(), Error> {
+        self.file.finish(sync)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync()
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Wall clock boundaries that start a new file
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Boundary {
+    Hour,
+    Midnight,
+}
+
+/// Conditions besides the record count that start a new file
+#[derive(Clone, Debug, Default)]
+struct Rotation {
+    boundary: Option<Boundary>,
+    interval: Option<Duration>,
+    size: Option<u64>,
+}
+
+impl<'a> Rotation {
+    fn new(args: &ArgMatches<'a>) -> Result<Rotation, Error> {
+        let size = match args.value_of("file_size") {
+            Some(s) => Some(parse_size(s).ok_or_else(|| format_err!("Invalid file size: {}", s))?),
+            None => None,
+        };
+        let (interval, boundary) = match args.value_of("rotate") {
+            Some("hourly") => (None, Some(Boundary::Hour)),
+            Some("midnight") => (None, Some(Boundary::Midnight)),
+            Some(r) => {
+                let interval = parse_duration(r)
+                    .filter(|d| *d > Duration::zero())
+                    .ok_or_else(|| format_err!("Invalid rotation: {}", r))?;
+                (Some(interval), None)
+            }
+            None => (None, None),
+        };
+        Ok(Rotation {
+            boundary,
+            interval,
+            size,
+        })
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.boundary.is_some() || self.interval.is_some() || self.size.is_some()
+    }
+
+    /// A file opened at `opened` is complete at `now` if the interval elapsed
+    /// or a boundary was crossed
+    fn is_expired(&self, opened: &Tm, now: &Tm) -> bool {
+        let elapsed = self.interval.map(|i| *now - *opened >= i).unwrap_or(false);
+        let crossed = match self.boundary {
+            Some(Boundary::Hour) => {
+                (now.tm_year, now.tm_yday, now.tm_hour) != (opened.tm_year, opened.tm_yday, opened.tm_hour)
+            }
+            Some(Boundary::Midnight) => (now.tm_year, now.tm_yday) != (opened.tm_year, opened.tm_yday),
+            None => false,
+        };
+        elapsed || crossed
+    }
+
+    fn is_full(&self, size: u64) -> bool {
+        self.size.map(|s| size >= s).unwrap_or(false)
+    }
+}
+
+/// When output files are gzipped
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compress {
+    /// Compress files once they are complete
+    Rotated,
+    /// Write compressed files
+    Stream,
+}
+
+/// Compresses completed files in a thread to not block the pipeline
+struct Compressor {
+    append: bool,
+    /// Number of files queued and not reported yet
+    pending: usize,
+    queue: Option<(Sender<PathBuf>, Receiver<Result<PathBuf, Error>>)>,
+}
+
+impl Compressor {
+    fn new(append: bool) -> Compressor {
+        Compressor {
+            append,
+            pending: 0,
+            queue: None,
+        }
+    }
+
+    /// Queue `filename` for compression
+    fn add(&mut self, filename: PathBuf) -> Result<(), Error> {
+        if self.queue.is_none() {
+            let (tx, files) = channel::<PathBuf>();
+            let (done, rx) = channel();
+            let append = self.append;
+            thread::spawn(move || {
+                for filename in files {
+                    if done.send(gzip(&filename, append)).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.queue = Some((tx, rx));
+        }
+        if let Some((ref tx, _)) = self.queue {
+            tx.send(filename)
+                .map_err(|_| err_msg("Failed to queue file for compression"))?;
+            self.pending += 1;
+        }
+        Ok(())
+    }
+
+    /// Files compressed so far. Waits for all queued files if `wait` is set.
+    fn completed(&mut self, wait: bool) -> Result<Vec<PathBuf>, Error> {
+        let mut files = vec![];
+        if let Some((_, ref rx)) = self.queue {
+            while self.pending > 0 {
+                let result = if wait {
+                    rx.recv().map_err(|_| err_msg("Compression failed"))?
+                } else {
+                    match rx.try_recv() {
+                        Ok(result) => result,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Err(err_msg("Compression failed")),
+                    }
+                };
+                self.pending -= 1;
+                files.push(result?);
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Limits of the files written by a run. The oldest files are deleted first.
+#[derive(Clone, Debug, Default)]
+struct Retention {
+    files: Option<usize>,
+    size: Option<u64>,
+    /// Completed files and their size on disk
+    completed: VecDeque<(PathBuf, u64)>,
+}
+
+impl<'a> Retention {
+    fn new(args: &ArgMatches<'a>) -> Result<Retention, Error> {
+        let files = match args.value_of("keep_files") {
+            Some(n) => Some(usize::from_str(n)
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format_err!("Invalid number of files to keep: {}", n))?),
+            None => None,
+        };
+        let size = match args.value_of("keep_size") {
+            Some(s) => Some(parse_size(s).ok_or_else(|| format_err!("Invalid size to keep: {}", s))?),
+            None => None,
+        };
+        Ok(Retention {
+            files,
+            size,
+            completed: VecDeque::new(),
+        })
+    }
+
+    fn add(&mut self, filename: PathBuf) -> Result<(), Error> {
+        let size = fs::metadata(&filename)?.len();
+        self.completed.push_back((filename, size));
+        Ok(())
+    }
+
+    /// Delete the oldest files until the limits are kept. The number
+    /// of files includes the file that is written if `current` is set.
+    fn expire(&mut self, current: bool) -> Result<(), Error> {
+        let current = if current { 1 } else { 0 };
+        loop {
+            let size: u64 = self.completed.iter().map(|c| c.1).sum();
+            let exceeded = self.files.map(|n| self.completed.len() + current > n).unwrap_or(false)
+                || self.size.map(|s| size > s).unwrap_or(false);
+            if !exceeded {
+                return Ok(());
+            }
+            match self.completed.pop_front() {
+                Some((filename, _)) => fs::remove_file(&filename)
+                    .or_else(|e| if e.kind() == io::ErrorKind::NotFound { Ok(()) } else { Err(e) })
+                    .map_err(|e| format_err!("Failed to remove {}: {}", filename.display(), e))?,
+                None => return Ok(()),
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -233,59 +757,101 @@ enum FilenameFormat {
 }
 
 pub struct FileWriter {
+    append: bool,
+    compress: Option<Compress>,
+    compressor: Compressor,
     current_filename: PathBuf,
+    /// Enumeration of the next file. Files deleted by the retention are not reused.
+    file_index: u32,
     file_size: u64,
     filename: PathBuf,
     filename_format: FilenameFormat,
     format: Format,
+    highlights: Highlights,
     index: usize,
+    /// Time the current file was opened at
+    opened: Tm,
     progress: ProgressBar,
+    /// Renderer of the human format. Shared by all files to keep the column widths.
+    renderer: Option<Rc<RefCell<Terminal<Vec<u8>>>>>,
+    retention: Retention,
+    rotation: Rotation,
+    /// Interval of syncs to disk. Zero syncs after every record.
+    sync: Option<Duration>,
+    /// Time of the last sync
+    synced: Tm,
+    timestamps: Timestamps,
     writer: Option<Box<Writer>>,
 }
 
 impl<'a> FileWriter {
-    pub fn new(args: &ArgMatches<'a>) -> Result<Self, Error> {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile) -> Result<Self, Error> {
         let filename = args.value_of("output")
             .and_then(|f| Some(PathBuf::from(f)))
             .ok_or_else(|| err_msg("Invalid output filename!"))?;
+        Self::with_filename(args, profile, filename)
+    }
 
-        let records_per_file = args.value_of("records_per_file").and_then(|l| {
-            Regex::new(r"^(\d+)([kMG])$")
-                .unwrap()
-                .captures(l)
-                .and_then(|caps| {
-                    caps.get(1)
-                        .map(|m| m.as_str())
-                        .and_then(|size| u64::from_str(size).ok())
-                        .map(|size| (size, caps.get(2).map(|m| m.as_str())))
-                })
-                .and_then(|(size, suffix)| match suffix {
-                    Some("k") => Some(1_000 * size),
-                    Some("M") => Some(1_000_000 * size),
-                    Some("G") => Some(1_000_000_000 * size),
-                    _ => None,
-                })
-                .or_else(|| u64::from_str(l).ok())
-        });
+    /// Writer for dumps that starts a new file after each flush and
+    /// does not draw a progress bar
+    pub fn dump(args: &ArgMatches<'a>, profile: &Profile, filename: PathBuf) -> Result<Self, Error> {
+        let mut writer = Self::with_filename(args, profile, filename)?;
+        if let FilenameFormat::Single(overwrite) = writer.filename_format {
+            writer.filename_format = FilenameFormat::Date(overwrite, ::std::u64::MAX);
+        }
+        writer.progress = ProgressBar::hidden();
+        Ok(writer)
+    }
+
+    fn with_filename(args: &ArgMatches<'a>, profile: &Profile, filename: PathBuf) -> Result<Self, Error> {
+
+        let records_per_file = args.value_of("records_per_file").and_then(parse_size);
+        let rotation = Rotation::new(args)?;
+        let compress = if args.is_present("compress") {
+            match args.value_of("compress") {
+                Some("stream") => Some(Compress::Stream),
+                _ => Some(Compress::Rotated),
+            }
+        } else {
+            None
+        };
 
         let format = args.value_of("format")
             .and_then(|f| Format::from_str(f).ok())
             .unwrap_or(Format::Raw);
-        if format == Format::Human {
-            return Err(err_msg("Human format is unsupported when writing to files"));
-        }
+        let renderer = if format == Format::Human || format == Format::Template {
+            Some(Rc::new(RefCell::new(Terminal::file(args, profile)?)))
+        } else {
+            None
+        };
 
         let overwrite = args.is_present("overwrite");
+        let append = args.is_present("append");
+        if append && format == Format::Html {
+            return Err(err_msg("Appending to HTML files is not supported"));
+        }
+        let sync = if args.is_present("fsync") {
+            match args.value_of("fsync") {
+                Some(s) => Some(parse_duration(s)
+                    .filter(|d| *d > Duration::zero())
+                    .ok_or_else(|| format_err!("Invalid sync interval: {}", s))?),
+                None => Some(Duration::zero()),
+            }
+        } else {
+            None
+        };
 
         let records = records_per_file.unwrap_or(::std::u64::MAX);
         let filename_format = match args.value_of("filename_format") {
             Some("enumerate") => FilenameFormat::Enumerate(overwrite, records),
             Some("date") => FilenameFormat::Date(overwrite, records),
-            // If records per file is set, default to enumerated even if
-            // no file format argument is supplied.
+            // If records per file or a rotation is set, default to enumerated
+            // even if no file format argument is supplied.
             Some(_) | None => {
                 if let Some(n) = records_per_file {
                     FilenameFormat::Enumerate(overwrite, n)
+                } else if rotation.is_enabled() {
+                    FilenameFormat::Enumerate(overwrite, records)
                 } else {
                     FilenameFormat::Single(overwrite)
                 }
@@ -315,21 +881,33 @@ impl<'a> FileWriter {
         };
 
         Ok(FileWriter {
+            append,
+            compress,
+            compressor: Compressor::new(append),
             current_filename: filename.clone(),
+            file_index: 0,
             file_size: 0,
             filename: filename,
             filename_format: filename_format,
             format: format,
+            highlights: Highlights::new(args, profile, Color::Indexed(YELLOW))?,
             index: 0,
+            opened: now(),
             progress: progress,
+            renderer,
+            retention: Retention::new(args)?,
+            rotation,
+            sync,
+            synced: now(),
+            timestamps: Timestamps::new(args)?,
             writer: None,
         })
     }
 
-    fn next_file(&self) -> Result<PathBuf, Error> {
+    fn next_file(&mut self) -> Result<PathBuf, Error> {
         match self.filename_format {
             FilenameFormat::Single(overwrite) => {
-                if self.filename.exists() && !overwrite {
+                if self.exists(&self.filename) && !overwrite && !self.append {
                     Err(format_err!(
                         "{} exists. Use overwrite flag to force!",
                         self.filename.display()
@@ -369,9 +947,10 @@ impl<'a> FileWriter {
                     Ok(dir.join(name))
                 };
 
-                for index in 0.. {
+                for index in self.file_index.. {
                     let n = next(index)?;
-                    if !n.exists() {
+                    if !self.exists(&n) {
+                        self.file_index = index + 1;
                         return Ok(n);
                     }
                 }
@@ -406,7 +985,7 @@ impl<'a> FileWriter {
                         .ok_or_else(|| err_msg("Invalid path"))?;
                     let candidate = PathBuf::from(format!("{}{}_{}", now, enumeration, filename));
                     let candidate = dir.join(candidate);
-                    if !overwrite && candidate.exists() {
+                    if !overwrite && self.exists(&candidate) {
                         e = Some(e.unwrap_or(0) + 1);
                         continue;
                     } else {
@@ -417,35 +996,115 @@ impl<'a> FileWriter {
         }
     }
 
-    fn write(&mut self, record: &Record) -> Result<(), Error> {
+    /// Check if `filename` or its compressed variant exists
+    fn exists(&self, filename: &Path) -> bool {
+        filename.exists() || (self.compress.is_some() && gz(filename).exists())
+    }
+
+    /// Write the index page of a html rotation that links all files of this run
+    fn write_index(&self) -> Result<(), Error> {
+        if self.format != Format::Html {
+            return Ok(());
+        }
+        if let FilenameFormat::Single(_) = self.filename_format {
+            return Ok(());
+        }
+        let stem = self.filename
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| err_msg("Invalid path"))?;
+        let filename = self.filename.with_file_name(format!("{}-index.html", stem));
+        let files = self.retention
+            .completed
+            .iter()
+            .map(|c| &c.0)
+            .chain(self.current_file())
+            .filter_map(|f| f.file_name())
+            .map(|f| f.to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+        let mut data: Map<String, Json> = Map::new();
+        data.insert("files".to_owned(), to_json(&files));
+        let mut output = Output::create(&filename, false)?;
+        Handlebars::new()
+            .template_renderw(INDEX, &data, &mut output)
+            .map_err(|e| format_err!("Rendering error: {}", e))?;
+        output.finish(self.sync.is_some())
+    }
+
+    /// File that is currently written if any
+    pub fn current_file(&self) -> Option<&PathBuf> {
+        self.writer.as_ref().map(|_| &self.current_filename)
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let timestamp = match record.timestamp {
+            Some(ref ts) if self.timestamps.mode() != Mode::Absolute => {
+                Some(self.timestamps.format(ts, self.format.timestamp_format()))
+            }
+            _ => None,
+        };
+        let timestamp = timestamp.as_ref().map(|t| t.as_str());
+
+        // The first record after a interval or boundary starts a new file
+        if self.writer.is_some() && self.rotation.is_expired(&self.opened, &now()) {
+            self.flush()?;
+        }
+
         match self.writer {
             Some(ref mut writer) => {
-                writer.write(record, self.index)?;
+                writer.write(record, timestamp, self.index)?;
                 self.index += 1;
             }
             None => {
+                self.compressed(false)?;
+                self.retention.expire(true)?;
                 self.current_filename = self.next_file()?;
+                if self.compress == Some(Compress::Stream) && !is_gz(&self.current_filename) {
+                    self.current_filename = gz(&self.current_filename);
+                }
                 let mut writer = match self.format {
-                    Format::Csv | Format::Json | Format::Raw => {
-                        Textfile::new(&self.current_filename, &self.format)? as Box<Writer>
+                    Format::Html => {
+                        let mut html = Html::new(&self.current_filename, &self.format, self.append)?;
+                        html.highlights = self.highlights.clone();
+                        html as Box<Writer>
                     }
-                    Format::Html => Html::new(&self.current_filename, &self.format)? as Box<Writer>,
-                    Format::Human => panic!("Unsupported format human in output file"),
+                    Format::Human | Format::Template => {
+                        let mut human = Humanfile::new(&self.current_filename, &self.format, self.append)?;
+                        human.renderer = self.renderer.clone();
+                        human as Box<Writer>
+                    }
+                    _ => Textfile::new(&self.current_filename, &self.format, self.append)? as Box<Writer>,
                 };
+                self.opened = now();
                 let message = format!("Writing {}", self.current_filename.display());
                 self.progress.set_message(&message);
-                writer.write(record, self.index)?;
+                writer.write(record, timestamp, self.index)?;
                 self.index += 1;
                 self.writer = Some(writer);
+                self.write_index()?;
             }
         }
 
         self.file_size += 1;
         self.progress.set_position(self.file_size);
 
+        if let Some(interval) = self.sync {
+            let now = now();
+            if now - self.synced >= interval {
+                if let Some(ref mut writer) = self.writer {
+                    writer.sync()?;
+                }
+                self.synced = now;
+            }
+        }
+
+        let full = self.writer
+            .as_ref()
+            .map(|w| self.rotation.is_full(w.size()))
+            .unwrap_or(false);
         match self.filename_format {
             FilenameFormat::Enumerate(_, n) | FilenameFormat::Date(_, n) => {
-                if self.file_size >= n {
+                if self.file_size >= n || full {
                     self.flush()
                 } else {
                     Ok(())
@@ -455,18 +1114,56 @@ impl<'a> FileWriter {
         }
     }
 
-    fn flush(&mut self) -> Result<(), Error> {
+    pub fn flush(&mut self) -> Result<(), Error> {
         if let Some(ref mut writer) = self.writer {
-            writer.flush()?;
+            writer.flush(self.sync.is_some())?;
         }
         self.progress
             .set_style(ProgressStyle::default_bar().template("{msg:.dim.bold}"));
         self.progress
             .finish_with_message(&format!("Finished dumping {} records.", self.index));
         self.file_size = 0;
-        self.writer = None;
+        // The writer is dropped before the file is compressed
+        if self.writer.take().is_some() {
+            let filename = self.current_filename.clone();
+            if self.compress == Some(Compress::Rotated) && !is_gz(&filename) {
+                self.compressor.add(filename)?;
+            } else {
+                self.retention.add(filename)?;
+                self.write_index()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the files compressed so far to the retention. Waits for the
+    /// compression of all files if `wait` is set.
+    fn compressed(&mut self, wait: bool) -> Result<(), Error> {
+        let files = self.compressor.completed(wait)?;
+        if !files.is_empty() {
+            for filename in files {
+                self.retention.add(filename)?;
+            }
+            self.retention.expire(self.writer.is_some())?;
+            self.write_index()?;
+        }
         Ok(())
     }
+
+    /// Complete the current file and wait for the compression
+    fn finish(&mut self) -> Result<(), Error> {
+        self.flush()?;
+        self.compressed(true)
+    }
+}
+
+/// Complete the current file if the pipeline fails
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        if self.writer.is_some() || self.compressor.pending > 0 {
+            self.finish().ok();
+        }
+    }
 }
 
 impl Sink for FileWriter {
@@ -485,4 +1182,9 @@ impl Sink for FileWriter {
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
         Ok(Async::Ready(()))
     }
+
+    /// Complete the current file at the end of the input
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.finish().map(Async::Ready)
+    }
 }
@@ -4,18 +4,403 @@
 // the terms of the Do What The Fuck You Want To Public License, Version 2, as
 // published by Sam Hocevar. See the COPYING file for more details.
 
+use adb;
+use aho_corasick::{AcAutomaton, Automaton, FullAcAutomaton};
 use clap::ArgMatches;
 use failure::Error;
 use profiles::*;
 use record::{Level, Record};
-use regex::Regex;
+use regex::{escape, Regex, RegexSet, RegexSetBuilder};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use timewindow::TimeWindow;
+
+/// Uids of applications start at this offset within the range of a user
+const FIRST_APPLICATION_UID: u32 = 10_000;
+/// Number of uids reserved for each user
+const PER_USER_RANGE: u32 = 100_000;
+
+lazy_static! {
+    // ActivityManager: "Start proc 1234:com.example/u0a12 for activity ..."
+    static ref START_PROC: Regex = Regex::new(r"^Start proc (\d+):([^/\s]+)/u(\d+)a(\d+) ").unwrap();
+    // ActivityManager (pre N): "Start proc com.example for activity ...: pid=1234 uid=10012 ..."
+    static ref START_PROC_LEGACY: Regex = Regex::new(r"^Start proc (\S+) .*: pid=(\d+) uid=(\d+)").unwrap();
+    // Event log tag am_proc_start: "[0,1234,10012,com.example,activity,...]"
+    static ref AM_PROC_START: Regex = Regex::new(r"^\[\d+,(\d+),(\d+),([^,]+),").unwrap();
+    // ActivityManager: "Process com.example (pid 1234) has died: fore TOP"
+    static ref PROC_DIED: Regex = Regex::new(r"^Process \S+ \(pid (\d+)\) has died").unwrap();
+    // Event log tag am_proc_died: "[0,1234,com.example,900,17]"
+    static ref AM_PROC_DIED: Regex = Regex::new(r"^\[\d+,(\d+),").unwrap();
+}
+
+/// Process started on the device as announced by the ActivityManager
+#[derive(Debug, PartialEq)]
+struct ProcStart {
+    pid: String,
+    uid: u32,
+    name: String,
+}
+
+impl ProcStart {
+    fn parse(record: &Record) -> Option<ProcStart> {
+        if record.tag == "am_proc_start" {
+            AM_PROC_START.captures(&record.message).and_then(|c| {
+                Some(ProcStart {
+                    pid: c[1].to_owned(),
+                    uid: c[2].parse().ok()?,
+                    name: c[3].to_owned(),
+                })
+            })
+        } else if record.tag == "ActivityManager" {
+            if let Some(c) = START_PROC.captures(&record.message) {
+                // "u<user>a<app>" is the uid of an application of a user
+                let user = c[3].parse::<u32>().ok()?;
+                let app = c[4].parse::<u32>().ok()?;
+                Some(ProcStart {
+                    pid: c[1].to_owned(),
+                    uid: user * PER_USER_RANGE + FIRST_APPLICATION_UID + app,
+                    name: c[2].to_owned(),
+                })
+            } else {
+                START_PROC_LEGACY.captures(&record.message).and_then(|c| {
+                    Some(ProcStart {
+                        pid: c[2].to_owned(),
+                        uid: c[3].parse().ok()?,
+                        name: c[1].to_owned(),
+                    })
+                })
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Pid of a process that died
+    fn died(record: &Record) -> Option<String> {
+        let regex: &Regex = match record.tag.as_str() {
+            "am_proc_died" => &AM_PROC_DIED,
+            "ActivityManager" => &PROC_DIED,
+            _ => return None,
+        };
+        regex.captures(&record.message).map(|c| c[1].to_owned())
+    }
+}
+
+/// Set of pids that belong to selected pids, packages or uids. The
+/// set follows the processes that are started and die on the device.
+struct Processes {
+    /// Pids selected on the command line
+    selected: HashSet<String>,
+    /// Pids of running processes of the packages and uids
+    pids: HashSet<String>,
+    packages: Vec<String>,
+    uids: Vec<u32>,
+    /// Packages and pids queried from the device in the background
+    resolved: Option<Receiver<(Vec<String>, Vec<String>)>>,
+}
+
+impl Processes {
+    fn new(pids: Vec<String>, packages: Vec<String>, uids: Vec<u32>) -> Processes {
+        Processes {
+            selected: pids.into_iter().collect(),
+            pids: HashSet::new(),
+            packages,
+            uids,
+            resolved: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.selected.is_empty() && self.packages.is_empty() && self.uids.is_empty()
+    }
+
+    /// Process names are either the package name or the package name
+    /// followed by a colon and a process suffix e.g "com.example:remote"
+    fn is_package(&self, name: &str) -> bool {
+        self.packages
+            .iter()
+            .any(|p| name == p || (name.starts_with(p.as_str()) && name[p.len()..].starts_with(':')))
+    }
+
+    /// Query the device for pids of running processes of the selected
+    /// packages and uids. The queries run in a thread to not delay the
+    /// start. Failures are ignored because the processes are tracked
+    /// once they (re)start.
+    fn resolve(&mut self) {
+        if self.packages.is_empty() && self.uids.is_empty() {
+            return;
+        }
+        let uids = self.uids.clone();
+        let mut packages = self.packages.clone();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for uid in &uids {
+                packages.extend(Self::packages_of_uid(*uid));
+            }
+            let mut pids = vec![];
+            for package in &packages {
+                let p = Self::pidof(package);
+                pids.extend(if p.is_empty() { Self::ps(package) } else { p });
+            }
+            tx.send((packages, pids)).ok();
+        });
+        self.resolved = Some(rx);
+    }
+
+    /// Add the result of `resolve` once it is available
+    fn update(&mut self) {
+        let resolved = match self.resolved {
+            Some(ref rx) => match rx.try_recv() {
+                Ok(resolved) => Some(resolved),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => None,
+            },
+            None => return,
+        };
+        self.resolved = None;
+        if let Some((packages, pids)) = resolved {
+            for package in packages {
+                if !self.packages.contains(&package) {
+                    self.packages.push(package);
+                }
+            }
+            self.pids.extend(pids);
+        }
+    }
+
+    fn adb_shell(args: &[&str]) -> Option<String> {
+        let adb = adb().ok()?;
+        Command::new(adb)
+            .arg("shell")
+            .args(args)
+            .output()
+            .ok()
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+    }
+
+    fn pidof(package: &str) -> Vec<String> {
+        Self::adb_shell(&["pidof", package])
+            .map(|o| {
+                o.split_whitespace()
+                    .filter(|p| p.chars().all(|c| c.is_digit(10)))
+                    .map(|p| p.to_owned())
+                    .collect()
+            })
+            .unwrap_or_else(|| vec![])
+    }
+
+    /// Fallback for devices without pidof. The pid is the second and
+    /// the process name the last column of the ps output.
+    fn ps(package: &str) -> Vec<String> {
+        Self::adb_shell(&["ps"])
+            .map(|o| {
+                o.lines()
+                    .filter_map(|l| {
+                        let columns = l.split_whitespace().collect::<Vec<&str>>();
+                        match (columns.get(1), columns.last()) {
+                            (Some(pid), Some(name))
+                                if *name == package
+                                    || name.starts_with(&format!("{}:", package)) =>
+                            {
+                                Some((*pid).to_owned())
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|| vec![])
+    }
+
+    /// Parse "package:com.example uid:10012" lines of "pm list packages -U"
+    fn packages_of_uid(uid: u32) -> Vec<String> {
+        Self::adb_shell(&["pm", "list", "packages", "-U"])
+            .map(|o| {
+                o.lines()
+                    .filter_map(|l| {
+                        let mut columns = l.split_whitespace();
+                        let package = columns.next()?.split(':').last()?;
+                        let u = columns.next()?.split(':').last()?.parse::<u32>().ok()?;
+                        // The uids are listed for the first user
+                        if u == uid % PER_USER_RANGE {
+                            Some(package.to_owned())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|| vec![])
+    }
+
+    /// Follow the processes of the selected packages and uids. Pids of
+    /// processes that died or are reused by other processes are removed.
+    fn track(&mut self, record: &Record) {
+        if self.packages.is_empty() && self.uids.is_empty() {
+            return;
+        }
+        self.update();
+        if let Some(p) = ProcStart::parse(record) {
+            if self.uids.contains(&p.uid) || self.is_package(&p.name) {
+                self.pids.insert(p.pid);
+            } else {
+                self.pids.remove(&p.pid);
+            }
+        } else if let Some(pid) = ProcStart::died(record) {
+            self.pids.remove(&pid);
+        }
+    }
+
+    fn contains(&self, pid: &str) -> bool {
+        self.selected.contains(pid) || self.pids.contains(pid)
+    }
+}
+
+/// Minimum levels per tag set with logcat filterspecs like
+/// "ActivityManager:I MyApp:V *:S". `None` silences a tag.
+#[derive(Debug, Default, PartialEq)]
+struct TagLevels {
+    levels: HashMap<String, Option<Level>>,
+    default: Option<Level>,
+}
+
+impl TagLevels {
+    fn new(specs: &[String]) -> Result<TagLevels, Error> {
+        let mut levels = HashMap::new();
+        // Unmatched tags are shown unless "*" is set
+        let mut default = Some(Level::None);
+        for spec in specs.iter().flat_map(|s| s.split_whitespace()) {
+            let mut split = spec.rsplitn(2, ':');
+            let (level, tag) = match (split.next(), split.next()) {
+                (Some(level), Some(tag)) => (level, tag),
+                (Some(tag), None) => ("V", tag),
+                _ => return Err(format_err!("Invalid filterspec: {}", spec)),
+            };
+            let level = match level {
+                // Verbose is the lowest priority on Android
+                "V" | "*" => Some(Level::None),
+                "D" | "I" | "W" | "E" | "F" => Some(Level::from(level)),
+                "S" => None,
+                _ => return Err(format_err!("Invalid level in filterspec: {}", spec)),
+            };
+            if tag == "*" {
+                default = level;
+            } else {
+                levels.insert(tag.to_owned(), level);
+            }
+        }
+        Ok(TagLevels { levels, default })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.levels.is_empty() && self.default == Some(Level::None)
+    }
+
+    fn allows(&self, record: &Record) -> bool {
+        match *self.levels.get(&record.tag).unwrap_or(&self.default) {
+            Some(ref level) => record.level >= *level,
+            None => false,
+        }
+    }
+}
+
+/// Case and fixed string options apply to a kind of filter if it is listed
+/// in the profile or on the command line. Command line options without a
+/// kind apply to tag and message filters.
+pub fn applies(args: &ArgMatches, profile: &[String], option: &str, kind: &str) -> bool {
+    profile.iter().any(|k| k == kind)
+        || (args.is_present(option)
+            && args.values_of(option)
+                .map(|mut v| v.len() == 0 || v.any(|k| k == kind))
+                .unwrap_or(true))
+}
+
+/// Compiled patterns of one polarity. Case sensitive fixed strings are
+/// searched with Aho-Corasick instead of a regex set. Aho-Corasick does
+/// not match empty strings.
+enum Set {
+    Literals(FullAcAutomaton<String>),
+    Regex(RegexSet),
+}
+
+impl Set {
+    fn new(patterns: Vec<String>, ignore_case: bool, fixed: bool) -> Result<Set, Error> {
+        if fixed && !ignore_case && patterns.iter().all(|p| !p.is_empty()) {
+            return Ok(Set::Literals(AcAutomaton::new(patterns).into_full()));
+        }
+        let patterns = if fixed {
+            patterns.iter().map(|p| escape(p)).collect()
+        } else {
+            patterns
+        };
+        RegexSetBuilder::new(&patterns)
+            .case_insensitive(ignore_case)
+            .build()
+            .map(Set::Regex)
+            .map_err(|e| format_err!("Invalid regex string: {}", e))
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Set::Literals(ref a) => a.len(),
+            Set::Regex(ref r) => r.len(),
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match *self {
+            Set::Literals(ref a) => a.find(text).next().is_some(),
+            Set::Regex(ref r) => r.is_match(text),
+        }
+    }
+}
+
+/// Positive and negative patterns of a filter compiled into sets. A
+/// text matches if it matches any positive (or there are none) and no
+/// negative pattern.
+pub struct Patterns {
+    positive: Set,
+    negative: Set,
+}
+
+impl Patterns {
+    pub fn new(i: &[String], ignore_case: bool, fixed: bool) -> Result<Patterns, Error> {
+        let mut positive = vec![];
+        let mut negative = vec![];
+        for r in i {
+            let (list, r) = if r.starts_with('!') {
+                (&mut negative, &r[1..])
+            } else {
+                (&mut positive, r.as_str())
+            };
+            list.push(r.to_owned());
+        }
+        Ok(Patterns {
+            positive: Set::new(positive, ignore_case, fixed)?,
+            negative: Set::new(negative, ignore_case, fixed)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positive.len() == 0 && self.negative.len() == 0
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        (self.positive.len() == 0 || self.positive.is_match(text)) && !self.negative.is_match(text)
+    }
+}
 
 pub struct Filter {
     level: Level,
-    message: Vec<Regex>,
-    message_negative: Vec<Regex>,
-    tag: Vec<Regex>,
-    tag_negative: Vec<Regex>,
+    tag_levels: TagLevels,
+    message: Patterns,
+    processes: Processes,
+    tag: Patterns,
+    thread: Vec<String>,
+    window: TimeWindow,
 }
 
 impl<'a> Filter {
@@ -29,73 +414,586 @@ impl<'a> Filter {
             .unwrap_or_else(|| vec![]);
         message_filter.extend(profile.message().clone());
 
-        let (tag, tag_negative) = Self::init_filter(&tag_filter)?;
-        let (message, message_negative) = Self::init_filter(&message_filter)?;
+        let tag = Self::init_filter(
+            &tag_filter,
+            applies(args, profile.ignore_case(), "ignore_case", "tag"),
+            applies(args, profile.fixed_strings(), "fixed_strings", "tag"),
+        )?;
+        let message = Self::init_filter(
+            &message_filter,
+            applies(args, profile.ignore_case(), "ignore_case", "message"),
+            applies(args, profile.fixed_strings(), "fixed_strings", "message"),
+        )?;
+
+        let pid = args.values_of("pid")
+            .map(|m| m.map(|f| f.to_owned()).collect::<Vec<String>>())
+            .unwrap_or_else(|| vec![]);
+        let thread = args.values_of("tid")
+            .map(|m| m.map(|f| f.to_owned()).collect::<Vec<String>>())
+            .unwrap_or_else(|| vec![]);
+        let package = args.values_of("package")
+            .map(|m| m.map(|f| f.to_owned()).collect::<Vec<String>>())
+            .unwrap_or_else(|| vec![]);
+        let uid = if args.is_present("uid") {
+            values_t!(args.values_of("uid"), u32)?
+        } else {
+            vec![]
+        };
+
+        // Like logcat the environment is only used without specs on the command line
+        let mut filterspec = profile.filterspec().clone();
+        match args.values_of("filterspec") {
+            Some(specs) => filterspec.extend(specs.map(|s| s.to_owned())),
+            None => filterspec.extend(env::var("ANDROID_LOG_TAGS").ok()),
+        }
+
+        let mut processes = Processes::new(pid, package, uid);
+        // Running processes can only be queried if rogcat captures a device
+        if !args.is_present("input") && !args.is_present("COMMAND") {
+            processes.resolve();
+        }
 
         Ok(Filter {
             level: Level::from(args.value_of("level").unwrap_or("")),
-            message: message,
-            message_negative: message_negative,
-            tag: tag,
-            tag_negative: tag_negative,
+            tag_levels: TagLevels::new(&filterspec)?,
+            message,
+            processes,
+            tag,
+            thread,
+            window: TimeWindow::new(args)?,
         })
     }
 
-    fn init_filter(i: &[String]) -> Result<(Vec<Regex>, Vec<Regex>), Error> {
-        let mut positive = vec![];
-        let mut negative = vec![];
-        for r in i {
-            if r.starts_with('!') {
-                let r = &r[1..];
-                negative
-                    .push(Regex::new(r).map_err(|_| format_err!("Invalid regex string: {}", r))?)
-            } else {
-                positive
-                    .push(Regex::new(r).map_err(|_| format_err!("Invalid regex string: {}", r))?)
+    /// Filter that is edited while running e.g in the pager. `line` takes
+    /// the tag, message and level options of the command line like
+    /// "-t ActivityManager -m !Displayed -l W". Other words are message
+    /// patterns.
+    pub fn live(line: &str) -> Result<Self, Error> {
+        let mut level = Level::None;
+        let mut message = vec![];
+        let mut tag = vec![];
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            let mut value = |option: &str| {
+                words
+                    .next()
+                    .ok_or_else(|| format_err!("Missing value of {}", option))
+            };
+            match word {
+                "-l" | "--level" => {
+                    let l = value(word)?;
+                    if !Level::values().contains(&l) {
+                        return Err(format_err!("Invalid level: {}", l));
+                    }
+                    level = Level::from(l);
+                }
+                "-m" | "--message" => message.push(value(word)?.to_owned()),
+                "-t" | "--tag" => tag.push(value(word)?.to_owned()),
+                w => message.push(w.to_owned()),
             }
         }
-        Ok((positive, negative))
+
+        Ok(Filter {
+            level,
+            tag_levels: TagLevels::new(&[])?,
+            message: Self::init_filter(&message, false, false)?,
+            processes: Processes::new(vec![], vec![], vec![]),
+            tag: Self::init_filter(&tag, false, false)?,
+            thread: vec![],
+            window: TimeWindow::default(),
+        })
+    }
+
+    pub fn window(&self) -> &TimeWindow {
+        &self.window
+    }
+
+    fn init_filter(i: &[String], ignore_case: bool, fixed: bool) -> Result<Patterns, Error> {
+        Patterns::new(i, ignore_case, fixed)
     }
 
     pub fn filter(&mut self, record: &Option<Record>) -> bool {
-        if let Some(ref record) = *record {
-            if record.level < self.level {
-                return false;
-            }
+        match *record {
+            Some(ref record) => self.matches(record),
+            None => true,
+        }
+    }
 
-            if !self.message.is_empty() && !self.message.iter().any(|m| m.is_match(&record.message))
-            {
-                return false;
-            }
+    pub fn matches(&mut self, record: &Record) -> bool {
+        // Process start records are needed even if they are filtered out
+        self.processes.track(record);
 
-            if self.message_negative
-                .iter()
-                .any(|m| m.is_match(&record.message))
-            {
-                return false;
-            }
+        if !self.window.contains(record) {
+            return false;
+        }
 
-            if !self.tag.is_empty() && !self.tag.iter().any(|m| m.is_match(&record.tag)) {
-                return false;
-            }
+        if record.level < self.level {
+            return false;
+        }
 
-            if self.tag_negative.iter().any(|m| m.is_match(&record.tag)) {
-                return false;
-            }
+        if !self.tag_levels.is_empty() && !self.tag_levels.allows(record) {
+            return false;
+        }
 
-            true
+        if !self.processes.is_empty() && !self.processes.contains(&record.process) {
+            return false;
+        }
+
+        if !self.thread.is_empty() && !self.thread.contains(&record.thread) {
+            return false;
+        }
+
+        if !self.message.is_empty() && !self.message.is_match(&record.message) {
+            return false;
+        }
+
+        if !self.tag.is_empty() && !self.tag.is_match(&record.tag) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Context lines before and after matching records like grep -A/-B/-C
+pub struct Context {
+    after: usize,
+    after_left: usize,
+    before: usize,
+    buffer: VecDeque<(u64, Record)>,
+    index: u64,
+    last: Option<u64>,
+}
+
+impl<'a> Context {
+    pub fn new(args: &ArgMatches<'a>) -> Result<Self, Error> {
+        let context = if args.is_present("context") {
+            value_t!(args, "context", usize)?
+        } else {
+            0
+        };
+        let after = if args.is_present("after_context") {
+            value_t!(args, "after_context", usize)?
+        } else {
+            context
+        };
+        let before = if args.is_present("before_context") {
+            value_t!(args, "before_context", usize)?
+        } else {
+            context
+        };
+        Ok(Context::with(before, after))
+    }
+
+    fn with(before: usize, after: usize) -> Context {
+        Context {
+            after,
+            after_left: 0,
+            before,
+            buffer: VecDeque::with_capacity(before + 1),
+            index: 0,
+            last: None,
+        }
+    }
+
+    /// Returns the records to emit for `record`. Unmatched records are
+    /// kept until they are either needed as context or dropped.
+    pub fn process(&mut self, record: Option<Record>, matched: bool) -> Vec<Option<Record>> {
+        let record = match record {
+            Some(r) => r,
+            None => return vec![None],
+        };
+
+        if self.before == 0 && self.after == 0 {
+            return if matched { vec![Some(record)] } else { vec![] };
+        }
+
+        let index = self.index;
+        self.index += 1;
+
+        if matched {
+            let record = Record {
+                context: Some(false),
+                ..record
+            };
+            let mut result = Vec::with_capacity(self.buffer.len() + 2);
+            let first = self.buffer.front().map(|&(i, _)| i).unwrap_or(index);
+            if let Some(last) = self.last {
+                if first > last + 1 {
+                    result.push(Some(Record {
+                        context: Some(false),
+                        ..Record::separator()
+                    }));
+                }
+            }
+            result.extend(self.buffer.drain(..).map(|(_, mut r)| {
+                r.context = Some(true);
+                Some(r)
+            }));
+            result.push(Some(record));
+            self.after_left = self.after;
+            self.last = Some(index);
+            result
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            self.last = Some(index);
+            vec![Some(Record {
+                context: Some(true),
+                ..record
+            })]
         } else {
-            true
+            if self.before > 0 {
+                if self.buffer.len() == self.before {
+                    self.buffer.pop_front();
+                }
+                self.buffer.push_back((index, record));
+            }
+            vec![]
         }
     }
 }
 
 #[test]
 fn filter_args() {
-    assert!(Filter::init_filter(&vec![]).is_ok());
-    assert!(Filter::init_filter(&vec!["".to_owned()]).is_ok());
-    assert!(Filter::init_filter(&vec!["a".to_owned()]).is_ok());
-    assert!(Filter::init_filter(&vec![".*".to_owned()]).is_ok());
-    assert!(Filter::init_filter(&vec![".*".to_owned(), ".*".to_owned()]).is_ok());
-    assert!(Filter::init_filter(&vec!["(".to_owned()]).is_err());
+    assert!(Filter::init_filter(&vec![], false, false).is_ok());
+    assert!(Filter::init_filter(&vec!["".to_owned()], false, false).is_ok());
+    assert!(Filter::init_filter(&vec!["a".to_owned()], false, false).is_ok());
+    assert!(Filter::init_filter(&vec![".*".to_owned()], false, false).is_ok());
+    assert!(Filter::init_filter(&vec![".*".to_owned(), ".*".to_owned()], false, false).is_ok());
+    assert!(Filter::init_filter(&vec!["(".to_owned()], false, false).is_err());
+    assert!(Filter::init_filter(&vec!["(".to_owned()], false, true).is_ok());
+}
+
+#[test]
+fn patterns() {
+    let p = Patterns::new(&[], false, false).unwrap();
+    assert!(p.is_empty());
+    assert!(p.is_match("A"));
+
+    let p = Patterns::new(&["^A".to_owned(), "B$".to_owned(), "!C".to_owned()], false, false)
+        .unwrap();
+    assert!(p.is_match("Ax"));
+    assert!(p.is_match("xB"));
+    assert!(!p.is_match("AC"));
+    assert!(!p.is_match("a"));
+
+    let p = Patterns::new(&["^A".to_owned(), "!c".to_owned()], true, false).unwrap();
+    assert!(p.is_match("ab"));
+    assert!(!p.is_match("aC"));
+
+    let p = Patterns::new(&["a.b".to_owned(), "!(".to_owned()], false, true).unwrap();
+    assert!(p.is_match("xa.b"));
+    assert!(!p.is_match("axb"));
+    assert!(!p.is_match("a.b("));
+
+    let p = Patterns::new(&["a.b".to_owned(), "!(".to_owned()], true, true).unwrap();
+    assert!(p.is_match("xA.B"));
+    assert!(!p.is_match("aXb"));
+
+    let p = Patterns::new(&["".to_owned()], false, true).unwrap();
+    assert!(p.is_match(""));
+    assert!(p.is_match("a"));
+}
+
+#[test]
+fn tag_levels() {
+    let record = |tag: &str, level: Level| Record {
+        tag: tag.to_owned(),
+        level,
+        ..Default::default()
+    };
+
+    let t = TagLevels::new(&[]).unwrap();
+    assert!(t.is_empty());
+    assert!(t.allows(&record("", Level::None)));
+
+    let t = TagLevels::new(&["ActivityManager:I MyApp:V".to_owned(), "*:S".to_owned()]).unwrap();
+    assert!(!t.is_empty());
+    assert!(t.allows(&record("ActivityManager", Level::Info)));
+    assert!(t.allows(&record("ActivityManager", Level::Error)));
+    assert!(!t.allows(&record("ActivityManager", Level::Debug)));
+    assert!(t.allows(&record("MyApp", Level::Verbose)));
+    assert!(!t.allows(&record("Other", Level::Fatal)));
+
+    let t = TagLevels::new(&["a:b:W".to_owned(), "B".to_owned(), "*:E".to_owned()]).unwrap();
+    assert!(t.allows(&record("a:b", Level::Warn)));
+    assert!(t.allows(&record("B", Level::Debug)));
+    assert!(!t.allows(&record("C", Level::Warn)));
+
+    assert!(TagLevels::new(&["A:X".to_owned()]).is_err());
+}
+
+#[test]
+fn live_filter() {
+    let record = |tag: &str, level: Level, message: &str| Record {
+        tag: tag.to_owned(),
+        level,
+        message: message.to_owned(),
+        ..Default::default()
+    };
+
+    let mut f = Filter::live("-t ^Activity --level W !Displayed").unwrap();
+    assert!(f.matches(&record("ActivityManager", Level::Warn, "Start")));
+    assert!(!f.matches(&record("ActivityManager", Level::Info, "Start")));
+    assert!(!f.matches(&record("PackageManager", Level::Error, "Start")));
+    assert!(!f.matches(&record("ActivityManager", Level::Error, "Displayed")));
+
+    let mut f = Filter::live("-m A B").unwrap();
+    assert!(f.matches(&record("", Level::None, "B")));
+    assert!(!f.matches(&record("", Level::None, "C")));
+
+    assert!(Filter::live("-l X").is_err());
+    assert!(Filter::live("-t").is_err());
+    assert!(Filter::live("-m (").is_err());
+}
+
+#[test]
+fn parse_proc_start() {
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Start proc 2342:com.example:remote/u0a57 for service com.example/.Service"
+            .to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(
+        ProcStart::parse(&record),
+        Some(ProcStart {
+            pid: "2342".to_owned(),
+            uid: 10_057,
+            name: "com.example:remote".to_owned(),
+        })
+    );
+
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Start proc com.example for activity com.example/.Main: pid=1234 uid=10012 \
+                  gids={50012, 3003}"
+            .to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(
+        ProcStart::parse(&record),
+        Some(ProcStart {
+            pid: "1234".to_owned(),
+            uid: 10_012,
+            name: "com.example".to_owned(),
+        })
+    );
+
+    let record = Record {
+        tag: "am_proc_start".to_owned(),
+        message: "[0,4711,10012,com.example,activity,{com.example/com.example.Main}]".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(
+        ProcStart::parse(&record),
+        Some(ProcStart {
+            pid: "4711".to_owned(),
+            uid: 10_012,
+            name: "com.example".to_owned(),
+        })
+    );
+
+    // Application of a secondary user
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Start proc 2342:com.example/u10a57 for activity com.example/.Main".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(ProcStart::parse(&record).unwrap().uid, 1_010_057);
+
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Killing 1234:com.example/u0a12 (adj 900): empty #17".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(ProcStart::parse(&record), None);
+    assert_eq!(ProcStart::died(&record), None);
+
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Process com.example (pid 1234) has died: fore TOP".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(ProcStart::died(&record), Some("1234".to_owned()));
+
+    let record = Record {
+        tag: "am_proc_died".to_owned(),
+        message: "[0,4711,com.example,900,17]".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(ProcStart::died(&record), Some("4711".to_owned()));
+}
+
+#[test]
+fn track_package() {
+    let mut processes = Processes::new(vec![], vec!["com.example".to_owned()], vec![]);
+    assert!(!processes.is_empty());
+    assert!(!processes.contains("1234"));
+
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Start proc 1234:com.example/u0a12 for activity com.example/.Main".to_owned(),
+        ..Default::default()
+    };
+    processes.track(&record);
+    assert!(processes.contains("1234"));
+
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Start proc 1235:com.example.other/u0a13 for activity com.example/.Main"
+            .to_owned(),
+        ..Default::default()
+    };
+    processes.track(&record);
+    assert!(!processes.contains("1235"));
+
+    // The pid is reused by another process
+    assert!(processes.contains("1234"));
+    let record = Record {
+        tag: "am_proc_start".to_owned(),
+        message: "[0,1234,10013,com.other,activity,{com.other/com.other.Main}]".to_owned(),
+        ..Default::default()
+    };
+    processes.track(&record);
+    assert!(!processes.contains("1234"));
+
+    let record = Record {
+        tag: "am_proc_start".to_owned(),
+        message: "[0,1236,10012,com.example:remote,service,{com.example/com.example.Service}]"
+            .to_owned(),
+        ..Default::default()
+    };
+    processes.track(&record);
+    assert!(processes.contains("1236"));
+    let record = Record {
+        tag: "ActivityManager".to_owned(),
+        message: "Process com.example:remote (pid 1236) has died: svc SVC".to_owned(),
+        ..Default::default()
+    };
+    processes.track(&record);
+    assert!(!processes.contains("1236"));
+
+    // Selected pids are kept
+    let mut processes = Processes::new(vec!["1236".to_owned()], vec!["com.example".to_owned()], vec![]);
+    processes.track(&record);
+    assert!(processes.contains("1236"));
+}
+
+#[test]
+fn context() {
+    let records = (0..10)
+        .map(|i| {
+            Some(Record {
+                message: format!("{}", i),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<Option<Record>>>();
+    let messages = |c: &mut Context, matches: &[usize]| -> Vec<String> {
+        records
+            .iter()
+            .enumerate()
+            .flat_map(|(i, r)| c.process(r.clone(), matches.contains(&i)))
+            .map(|r| {
+                let r = r.unwrap();
+                format!("{}{}", if r.is_context() { "-" } else { "" }, r.message)
+            })
+            .collect()
+    };
+
+    assert_eq!(messages(&mut Context::with(0, 0), &[1, 5]), vec!["1", "5"]);
+    assert_eq!(
+        messages(&mut Context::with(1, 1), &[1, 5]),
+        vec!["-0", "1", "-2", "--", "-4", "5", "-6"]
+    );
+    assert_eq!(
+        messages(&mut Context::with(2, 0), &[3, 4]),
+        vec!["-1", "-2", "3", "4"]
+    );
+    assert_eq!(
+        messages(&mut Context::with(0, 2), &[1, 3]),
+        vec!["1", "-2", "3", "-4", "-5"]
+    );
+    assert_eq!(messages(&mut Context::with(1, 1), &[4, 6]), vec!["-3", "4", "-5", "6", "-7"]);
+}
+
+#[cfg(all(test, feature = "nightly"))]
+mod bench {
+    use super::Patterns;
+    use regex::Regex;
+    use test::{black_box, Bencher};
+
+    /// Filter of a larger profile e.g all services of a system with
+    /// some noise excluded
+    fn patterns() -> Vec<String> {
+        (0..100)
+            .map(|i| format!("Service{} started", i))
+            .chain((0..20).map(|i| format!("!debug{}", i)))
+            .collect()
+    }
+
+    /// Mostly unmatched messages like in a full logcat
+    fn messages() -> Vec<String> {
+        (0..100)
+            .map(|i| {
+                if i % 10 == 0 {
+                    format!("Service{} started in {} ms", i, i * 3)
+                } else {
+                    format!("Something happened in component {} with id {:x}", i, i * 4711)
+                }
+            })
+            .collect()
+    }
+
+    /// Matching each regex one by one as done before the sets were introduced
+    #[bench]
+    fn regex_list(b: &mut Bencher) {
+        let patterns = patterns();
+        let positive = patterns
+            .iter()
+            .filter(|p| !p.starts_with('!'))
+            .map(|p| Regex::new(p).unwrap())
+            .collect::<Vec<Regex>>();
+        let negative = patterns
+            .iter()
+            .filter(|p| p.starts_with('!'))
+            .map(|p| Regex::new(&p[1..]).unwrap())
+            .collect::<Vec<Regex>>();
+        let is_match = |m: &str| {
+            positive.iter().any(|r| r.is_match(m)) && !negative.iter().any(|r| r.is_match(m))
+        };
+        let messages = messages();
+        assert_eq!(messages.iter().filter(|m| is_match(m)).count(), 10);
+        b.iter(|| {
+            for m in &messages {
+                black_box(is_match(m));
+            }
+        });
+    }
+
+    #[bench]
+    fn regex_set(b: &mut Bencher) {
+        let patterns = Patterns::new(&patterns(), false, false).unwrap();
+        let messages = messages();
+        assert_eq!(messages.iter().filter(|m| patterns.is_match(m)).count(), 10);
+        b.iter(|| {
+            for m in &messages {
+                black_box(patterns.is_match(m));
+            }
+        });
+    }
+
+    /// Aho-Corasick for fixed strings
+    #[bench]
+    fn literal_set(b: &mut Bencher) {
+        let patterns = Patterns::new(&patterns(), false, true).unwrap();
+        let messages = messages();
+        assert_eq!(messages.iter().filter(|m| patterns.is_match(m)).count(), 10);
+        b.iter(|| {
+            for m in &messages {
+                black_box(patterns.is_match(m));
+            }
+        });
+    }
 }
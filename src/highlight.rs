@@ -0,0 +1,191 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use config_get;
+use failure::Error;
+use filter::applies;
+use profiles::Profile;
+use record::Record;
+use regex::{escape, Regex, RegexBuilder};
+use std::str::FromStr;
+use theme::Color;
+
+/// Pattern with the color its matches are highlighted with
+#[derive(Clone, Debug)]
+struct Highlight {
+    color: Color,
+    regex: Regex,
+}
+
+impl Highlight {
+    /// Parse `PATTERN` or `COLOR=PATTERN`
+    fn new(spec: &str, default: Color) -> Result<Highlight, Error> {
+        let (color, pattern) = match spec.find('=') {
+            Some(n) => match Color::from_str(&spec[..n]) {
+                Ok(color) => (color, &spec[n + 1..]),
+                Err(_) => (default, spec),
+            },
+            None => (default, spec),
+        };
+        Ok(Highlight {
+            color,
+            regex: Regex::new(pattern).map_err(|_| format_err!("Invalid regex string: {}", pattern))?,
+        })
+    }
+}
+
+/// Highlight patterns of the command line and profile and optionally the
+/// positive message and tag filters
+#[derive(Clone, Debug, Default)]
+pub struct Highlights {
+    message: Vec<Highlight>,
+    tag: Vec<Highlight>,
+}
+
+impl<'a> Highlights {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile, default: Color) -> Result<Self, Error> {
+        let mut highlights = profile.highlight().clone();
+        if let Some(values) = args.values_of("highlight") {
+            highlights.extend(values.map(|v| v.to_owned()));
+        }
+        let highlights = highlights
+            .iter()
+            .map(|h| Highlight::new(h, default))
+            .collect::<Result<Vec<Highlight>, Error>>()?;
+        let mut message = highlights.clone();
+        let mut tag = highlights;
+
+        if args.is_present("highlight_filter") || config_get("terminal_highlight_filter").unwrap_or(false) {
+            let filters = |kind: &str, patterns: &Vec<String>| -> Result<Vec<Highlight>, Error> {
+                let ignore_case = applies(args, profile.ignore_case(), "ignore_case", kind);
+                let fixed = applies(args, profile.fixed_strings(), "fixed_strings", kind);
+                args.values_of(kind)
+                    .map(|v| v.map(|f| f.to_owned()).collect::<Vec<String>>())
+                    .unwrap_or_else(|| vec![])
+                    .iter()
+                    .chain(patterns)
+                    .filter(|f| !f.starts_with('!'))
+                    .map(|f| {
+                        let pattern = if fixed { escape(f) } else { f.to_owned() };
+                        RegexBuilder::new(&pattern)
+                            .case_insensitive(ignore_case)
+                            .build()
+                            .map(|regex| Highlight {
+                                color: default,
+                                regex,
+                            })
+                            .map_err(|_| format_err!("Invalid regex string: {}", f))
+                    })
+                    .collect()
+            };
+            message.extend(filters("message", profile.message())?);
+            tag.extend(filters("tag", profile.tag())?);
+        }
+
+        Ok(Highlights { message, tag })
+    }
+
+    /// Color of the first pattern that matches the tag or message of `record`
+    pub fn is_match(&self, record: &Record) -> Option<Color> {
+        self.tag
+            .iter()
+            .find(|h| h.regex.is_match(&record.tag))
+            .or_else(|| self.message.iter().find(|h| h.regex.is_match(&record.message)))
+            .map(|h| h.color)
+    }
+
+    /// Highlight color of each char of `text`. Earlier patterns take precedence.
+    fn colors(highlights: &[Highlight], text: &str) -> Vec<Option<Color>> {
+        let mut colors = vec![None; text.len()];
+        for h in highlights {
+            for m in h.regex.find_iter(text) {
+                for c in colors[m.start()..m.end()].iter_mut().filter(|c| c.is_none()) {
+                    *c = Some(h.color);
+                }
+            }
+        }
+        // Translate byte into char indices
+        text.char_indices().map(|(i, _)| colors[i]).collect()
+    }
+
+    pub fn message(&self, text: &str) -> Vec<Option<Color>> {
+        Self::colors(&self.message, text)
+    }
+
+    pub fn tag(&self, text: &str) -> Vec<Option<Color>> {
+        Self::colors(&self.tag, text)
+    }
+}
+
+/// Split `text` into runs of chars with equal colors. `colors` holds
+/// the color of each char.
+pub fn runs<'a, T: Copy + PartialEq>(text: &'a str, colors: &[T]) -> Vec<(&'a str, T)> {
+    let mut runs = vec![];
+    let mut start = 0;
+    let mut indices = text.char_indices().map(|(i, _)| i).chain(Some(text.len()));
+    indices.next();
+    for (n, end) in indices.enumerate() {
+        let color = colors[n];
+        if colors.get(n + 1) != Some(&color) || end == text.len() {
+            runs.push((&text[start..end], color));
+            start = end;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+fn highlights(message: &[&str]) -> Highlights {
+    Highlights {
+        message: message
+            .iter()
+            .map(|m| Highlight::new(m, Color::Indexed(3)).unwrap())
+            .collect(),
+        tag: vec![],
+    }
+}
+
+#[test]
+fn highlight_spec() {
+    let h = Highlight::new("red=^foo", Color::Indexed(3)).unwrap();
+    assert_eq!(h.color, Color::Indexed(1));
+    assert_eq!(h.regex.as_str(), "^foo");
+    let h = Highlight::new("#ff8000=a=b", Color::Indexed(3)).unwrap();
+    assert_eq!(h.color, Color::Rgb(255, 128, 0));
+    assert_eq!(h.regex.as_str(), "a=b");
+    let h = Highlight::new("a=b", Color::Indexed(3)).unwrap();
+    assert_eq!(h.color, Color::Indexed(3));
+    assert_eq!(h.regex.as_str(), "a=b");
+    assert!(Highlight::new("red=(", Color::Indexed(3)).is_err());
+}
+
+#[test]
+fn highlight_runs() {
+    let h = highlights(&["red=b+", "c"]);
+    let colors = h.message("äbbcd");
+    assert_eq!(colors.len(), 5);
+    assert_eq!(
+        runs("äbbcd", &colors),
+        vec![
+            ("ä", None),
+            ("bb", Some(Color::Indexed(1))),
+            ("c", Some(Color::Indexed(3))),
+            ("d", None),
+        ]
+    );
+    assert_eq!(runs::<Option<Color>>("", &[]), vec![]);
+    // The first pattern wins on overlaps
+    let h = highlights(&["red=abc", "bcd"]);
+    assert_eq!(
+        runs("abcde", &h.message("abcde")),
+        vec![
+            ("abc", Some(Color::Indexed(1))),
+            ("d", Some(Color::Indexed(3))),
+            ("e", None),
+        ]
+    );
+}
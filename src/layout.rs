@@ -0,0 +1,286 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use failure::{err_msg, Error};
+use std::str::FromStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Layout of the human format if neither the profile nor the config set one
+pub const DEFAULT_LAYOUT: &str = "{timestamp} {diff} {tag} ({pid} {tid}) {level} {message}";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Buffer,
+    Device,
+    Diff,
+    Level,
+    Message,
+    Pid,
+    Tag,
+    Tid,
+    Timestamp,
+}
+
+impl FromStr for Field {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Field, Error> {
+        match s {
+            "buffer" => Ok(Field::Buffer),
+            "device" => Ok(Field::Device),
+            "diff" => Ok(Field::Diff),
+            "level" => Ok(Field::Level),
+            "message" => Ok(Field::Message),
+            "pid" => Ok(Field::Pid),
+            "tag" => Ok(Field::Tag),
+            "tid" => Ok(Field::Tid),
+            "timestamp" => Ok(Field::Timestamp),
+            _ => Err(format_err!("Invalid layout column: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Center,
+    Left,
+    Right,
+}
+
+/// How values exceeding the column width are shortened
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Truncate {
+    /// Cut the end
+    Clip,
+    /// Cut the beginning
+    ClipStart,
+    /// Cut the end and mark the cut with an ellipsis
+    Ellipsis,
+    /// Remove vowels and cut the end if still too long
+    Vowels,
+}
+
+impl FromStr for Truncate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Truncate, Error> {
+        match s {
+            "clip" => Ok(Truncate::Clip),
+            "clip-start" => Ok(Truncate::ClipStart),
+            "ellipsis" => Ok(Truncate::Ellipsis),
+            "vowels" => Ok(Truncate::Vowels),
+            _ => Err(format_err!("Invalid truncation: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub field: Field,
+    pub align: Option<Align>,
+    pub width: Option<usize>,
+    pub truncate: Option<Truncate>,
+}
+
+impl FromStr for Column {
+    type Err = Error;
+
+    /// Parse `name[:[<|>|^][width][:truncate]]`
+    fn from_str(s: &str) -> Result<Column, Error> {
+        let mut parts = s.split(':');
+        let field = Field::from_str(parts.next().unwrap_or_default().trim())?;
+        let mut align = None;
+        let mut width = None;
+        if let Some(spec) = parts.next() {
+            let spec = match spec.chars().next() {
+                Some('<') => {
+                    align = Some(Align::Left);
+                    &spec[1..]
+                }
+                Some('>') => {
+                    align = Some(Align::Right);
+                    &spec[1..]
+                }
+                Some('^') => {
+                    align = Some(Align::Center);
+                    &spec[1..]
+                }
+                _ => spec,
+            };
+            if !spec.is_empty() {
+                width = Some(usize::from_str(spec)
+                    .map_err(|_| format_err!("Invalid width of layout column {}", s))?);
+            }
+        }
+        let truncate = parts.next().map(Truncate::from_str).map_or(Ok(None), |t| t.map(Some))?;
+        if parts.next().is_some() {
+            return Err(format_err!("Invalid layout column: {}", s));
+        }
+        Ok(Column {
+            field,
+            align,
+            width,
+            truncate,
+        })
+    }
+}
+
+impl Column {
+    /// Shorten and pad `value` to `width` columns
+    pub fn format(&self, value: &str, width: usize, align: Align, truncate: Truncate) -> String {
+        let width = self.width.unwrap_or(width);
+        let value = shorten(value, width, self.truncate.unwrap_or(truncate));
+        let padding = width.saturating_sub(value.width());
+        match self.align.unwrap_or(align) {
+            Align::Left => format!("{}{}", value, " ".repeat(padding)),
+            Align::Right => format!("{}{}", " ".repeat(padding), value),
+            Align::Center => format!(
+                "{}{}{}",
+                " ".repeat(padding / 2),
+                value,
+                " ".repeat(padding - padding / 2)
+            ),
+        }
+    }
+}
+
+/// Leading chars of `value` that fit into `width` columns
+fn take(value: &str, width: usize) -> String {
+    let mut columns = 0;
+    value
+        .chars()
+        .take_while(|c| {
+            columns += c.width().unwrap_or(0);
+            columns <= width
+        })
+        .collect()
+}
+
+fn shorten(value: &str, width: usize, truncate: Truncate) -> String {
+    if value.width() <= width {
+        return value.to_owned();
+    }
+    match truncate {
+        Truncate::Clip => take(value, width),
+        Truncate::ClipStart => {
+            let tail = take(&value.chars().rev().collect::<String>(), width);
+            tail.chars().rev().collect()
+        }
+        Truncate::Ellipsis if width > 0 => take(value, width - 1) + "…",
+        Truncate::Ellipsis => String::new(),
+        Truncate::Vowels => {
+            let value = value
+                .chars()
+                .filter(|c| !"aeiou".contains(*c))
+                .collect::<String>();
+            shorten(&value, width, Truncate::Clip)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Text(String),
+    Column(Column),
+}
+
+/// Columns of the human format. A layout is a string with columns in braces
+/// and literal text in between, e.g. "{timestamp} {tag:>20:ellipsis} {message}".
+/// The message column must be the last element.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    items: Vec<Item>,
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Layout, Error> {
+        let mut items = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end = rest.find('}')
+                        .ok_or_else(|| format_err!("Unterminated column in layout: {}", s))?;
+                    items.push(Item::Column(Column::from_str(&rest[1..end])?));
+                    rest = &rest[end + 1..];
+                }
+                Some(n) => {
+                    items.push(Item::Text(rest[..n].to_owned()));
+                    rest = &rest[n..];
+                }
+                None => {
+                    items.push(Item::Text(rest.to_owned()));
+                    rest = "";
+                }
+            }
+        }
+
+        let is_message = |i: &Item| match *i {
+            Item::Column(ref c) => c.field == Field::Message,
+            _ => false,
+        };
+        if !items.last().map(is_message).unwrap_or(false)
+            || items.iter().filter(|i| is_message(i)).count() != 1
+        {
+            return Err(err_msg("The message column must be the last element of a layout"));
+        }
+        Ok(Layout { items })
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::from_str(DEFAULT_LAYOUT).unwrap()
+    }
+}
+
+impl Layout {
+    /// Text and columns preceding the message
+    pub fn preamble(&self) -> &[Item] {
+        &self.items[..self.items.len() - 1]
+    }
+}
+
+#[test]
+fn parse_layout() {
+    let layout = Layout::from_str("{timestamp} [{tag:>20:ellipsis}] {message}").unwrap();
+    assert_eq!(layout.preamble().len(), 4);
+    assert_eq!(layout.preamble()[1], Item::Text(" [".to_owned()));
+    assert_eq!(
+        layout.preamble()[2],
+        Item::Column(Column {
+            field: Field::Tag,
+            align: Some(Align::Right),
+            width: Some(20),
+            truncate: Some(Truncate::Ellipsis),
+        })
+    );
+    assert!(Layout::from_str("{tag::vowels} {message}").is_ok());
+    assert!(Layout::from_str("{tag}").is_err());
+    assert!(Layout::from_str("{message} {tag}").is_err());
+    assert!(Layout::from_str("{foo} {message}").is_err());
+    assert!(Layout::from_str("{tag:x} {message}").is_err());
+    assert!(Layout::from_str("{tag {message}").is_err());
+    Layout::default();
+}
+
+#[test]
+fn format_column() {
+    let column = Column::from_str("tag").unwrap();
+    assert_eq!(column.format("abc", 5, Align::Right, Truncate::Clip), "  abc");
+    assert_eq!(column.format("abc", 5, Align::Center, Truncate::Clip), " abc ");
+    assert_eq!(column.format("abcdef", 4, Align::Left, Truncate::Clip), "abcd");
+    assert_eq!(column.format("abcdef", 4, Align::Left, Truncate::ClipStart), "cdef");
+    assert_eq!(column.format("abcdef", 4, Align::Left, Truncate::Ellipsis), "abc…");
+    assert_eq!(column.format("ActivityManager", 8, Align::Left, Truncate::Vowels), "ActvtyMn");
+    assert_eq!(column.format("日本語", 5, Align::Right, Truncate::Clip), " 日本");
+    assert_eq!(column.format("日本語", 5, Align::Left, Truncate::ClipStart), "本語 ");
+    assert_eq!(column.format("日本語", 4, Align::Left, Truncate::Ellipsis), "日… ");
+    let column = Column::from_str("tag:<6").unwrap();
+    assert_eq!(column.format("abc", 2, Align::Right, Truncate::Clip), "abc   ");
+}
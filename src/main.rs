@@ -6,7 +6,9 @@
 
 #![cfg_attr(feature = "clippy", feature(plugin))]
 #![cfg_attr(feature = "clippy", plugin(clippy))]
+#![cfg_attr(feature = "nightly", feature(test))]
 
+extern crate aho_corasick;
 extern crate atty;
 extern crate bytes;
 #[macro_use]
@@ -17,6 +19,7 @@ extern crate csv;
 extern crate directories;
 #[macro_use]
 extern crate failure;
+extern crate flate2;
 extern crate futures;
 extern crate handlebars;
 extern crate indicatif;
@@ -34,8 +37,12 @@ extern crate serde_json;
 extern crate serial;
 #[cfg(test)]
 extern crate tempdir;
+#[cfg(all(test, feature = "nightly"))]
+extern crate test;
 extern crate term;
 extern crate term_size;
+#[cfg(not(target_os = "windows"))]
+extern crate termion;
 extern crate time;
 extern crate tokio_core;
 #[macro_use]
@@ -43,6 +50,7 @@ extern crate tokio_io;
 extern crate tokio_process;
 extern crate tokio_signal;
 extern crate toml;
+extern crate unicode_width;
 extern crate url;
 extern crate which;
 extern crate zip;
@@ -50,40 +58,64 @@ extern crate zip;
 use cli::cli;
 use clap::ArgMatches;
 use config::Config;
+use dedup::Dedup;
 use failure::{err_msg, Error};
 use filewriter::FileWriter;
-use filter::Filter;
+use filter::{Context, Filter};
+use marker::markers;
 use futures::future::ok;
+use futures::stream::{empty, iter_ok, once};
 use futures::{Future, Sink, Stream};
+use outcome::Outcome;
 use parser::Parser;
-use profiles::Profiles;
+use profiles::{Profile, Profiles};
 use reader::{file_reader, serial_reader, stdin_reader, tcp_reader};
+use recorder::Recorder;
 use record::Record;
 use runner::runner;
 use std::env;
-use std::io::{stderr, Write};
+use std::io::{self, stderr, Write};
 use std::net::ToSocketAddrs;
 use std::path::PathBuf;
 use std::process::{exit, Command};
+use std::str::FromStr;
 use std::sync::RwLock;
+use std::time::Duration;
 use terminal::Terminal;
-use tokio_core::reactor::Core;
+use trigger::Triggers;
+use tokio_core::reactor::{Core, Handle, Timeout};
 use tokio_process::CommandExt;
 use url::Url;
+use utils::parse_duration;
 use which::which_in;
 
 mod bugreport;
 mod cli;
+mod dedup;
 mod devices;
+mod expect;
 mod filewriter;
 mod filter;
+mod highlight;
+mod layout;
 mod log;
+mod marker;
+mod outcome;
+#[cfg(not(target_os = "windows"))]
+mod pager;
 mod parser;
+mod pretty;
 mod profiles;
 mod reader;
+mod recorder;
 mod record;
 mod runner;
+mod template;
 mod terminal;
+mod theme;
+mod timestamps;
+mod timewindow;
+mod trigger;
 mod utils;
 #[cfg(test)]
 mod tests;
@@ -94,6 +126,7 @@ lazy_static! {
 
 pub type RSink = Box<Sink<SinkItem = Option<Record>, SinkError = Error>>;
 pub type RStream = Box<Stream<Item = std::option::Option<Record>, Error = Error>>;
+pub type RFuture = Box<Future<Item = (), Error = Error>>;
 
 const DEFAULT_BUFFER: [&str; 4] = ["main", "events", "crash", "kernel"];
 
@@ -164,6 +197,29 @@ fn input(core: &mut Core, args: &ArgMatches) -> Result<RStream, Error> {
     }
 }
 
+/// Interactive pager output, a stream that ends the input once the
+/// pager is closed and a future that resolves when it is closed
+#[cfg(not(target_os = "windows"))]
+fn pager(args: &ArgMatches, profile: &Profile, handle: &Handle) -> Result<(RSink, RStream, RFuture), Error> {
+    let pager = pager::Pager::new(args, profile, handle)?;
+    let quit = Box::new(pager.closed().into_stream().map(|_| None));
+    let closed = pager.closed();
+    Ok((Box::new(pager), quit, closed))
+}
+
+#[cfg(target_os = "windows")]
+fn pager(_: &ArgMatches, _: &Profile, _: &Handle) -> Result<(RSink, RStream, RFuture), Error> {
+    Err(err_msg("The interactive pager is not supported on Windows"))
+}
+
+fn is_broken_pipe(e: &Error) -> bool {
+    let io = match e.downcast_ref::<term::Error>() {
+        Some(&term::Error::Io(ref e)) => Some(e),
+        _ => e.downcast_ref::<io::Error>(),
+    };
+    io.map(|e| e.kind() == io::ErrorKind::BrokenPipe).unwrap_or(false)
+}
+
 fn run() -> Result<i32, Error> {
     let args = cli().get_matches();
     let config_file = config_dir().join("config.toml");
@@ -180,6 +236,7 @@ fn run() -> Result<i32, Error> {
         ("bugreport", Some(sub_matches)) => exit(bugreport::create(sub_matches, &mut core)?),
         ("completions", Some(sub_matches)) => exit(cli::subcommand_completions(sub_matches)?),
         ("devices", _) => exit(devices::devices(&mut core)?),
+        ("expect", Some(sub_matches)) => exit(expect::run(sub_matches, &profile, &mut core)?),
         ("log", Some(sub_matches)) => exit(log::run(sub_matches, &mut core)?),
         ("profiles", Some(sub_matches)) => exit(profiles.subcommand(sub_matches)?),
         (_, _) => (),
@@ -208,10 +265,42 @@ fn run() -> Result<i32, Error> {
         .flatten_stream()
         .map(|_| None)
         .map_err(|e| e.into());
+    let timeout = if let Some(t) = args.value_of("timeout") {
+        let duration = u64::from_str(t)
+            .ok()
+            .map(Duration::from_secs)
+            .or_else(|| parse_duration(t).and_then(|d| d.to_std().ok()))
+            .ok_or_else(|| format_err!("Invalid timeout: {}", t))?;
+        let timeout = Timeout::new(duration, &core.handle())?
+            .into_stream()
+            .map(|_| None)
+            .map_err(|e| e.into());
+        Box::new(timeout) as RStream
+    } else {
+        Box::new(empty()) as RStream
+    };
     let mut parser = Parser::new();
+    let mut outcome = Outcome::new(&args)?;
     let mut filter = Filter::new(&args, &profile)?;
+    let mut context = Context::new(&args)?;
+    let mut dedup = Dedup::new(&args, &profile)?;
+    let mut triggers = Triggers::new(&args, &profile, core.handle())?;
+    let recorder = Recorder::new(&args, &profile, &core.handle())?;
+    let markers = markers(&args, &core.handle())?;
+    let mut closed = None;
+    let mut quit = Box::new(empty()) as RStream;
+    let mut pager_process = None;
     let output = if args.is_present("output") {
-        Box::new(FileWriter::new(&args)?) as RSink
+        Box::new(FileWriter::new(&args, &profile)?) as RSink
+    } else if args.is_present("tui") {
+        let (pager, q, c) = pager(&args, &profile, &core.handle())?;
+        quit = q;
+        closed = Some(c);
+        pager
+    } else if args.is_present("pager") && atty::is(atty::Stream::Stdout) {
+        let (terminal, process) = Terminal::pager(&args, &profile)?;
+        pager_process = Some(process);
+        Box::new(terminal) as RSink
     } else {
         Box::new(Terminal::new(&args, &profile)?) as RSink
     };
@@ -221,8 +310,10 @@ fn run() -> Result<i32, Error> {
     } else {
         None
     };
-    let mut head = || {
+    // Markers do not count
+    let mut head = |m: &Option<Record>| {
         ok(match cnt {
+            _ if m.as_ref().map(|r| r.is_marker()).unwrap_or(false) => true,
             Some(0) => false,
             Some(_) => {
                 cnt = cnt.map(|s| s - 1);
@@ -232,13 +323,56 @@ fn run() -> Result<i32, Error> {
         })
     };
 
+    // Files are expected to be sorted: stop reading once the window is passed
+    let window = filter.window().clone();
+    let file_input = args.values_of("input")
+        .map(|mut i| i.all(|i| !i.starts_with("serial://")))
+        .unwrap_or(false);
+
+    // Stages end the stream by emitting None
     let result = input
         .select(ctrl_c)
+        .select(timeout)
+        // Closing the pager ends the run
+        .select(quit)
         .take_while(|i| ok(i.is_some()))
+        // Let stages flush pending records at the end of the input
+        .chain(once(Ok(None)))
         .and_then(|m| parser.process(m))
-        .filter(|m| filter.filter(m))
-        .take_while(|_| head())
+        .map(|m| if file_input && window.is_passed(&m) { None } else { m })
+        .and_then(|m| recorder.process(&m).map(|_| m))
+        .and_then(|m| triggers.process(&m).map(|_| m))
+        .map(|m| iter_ok::<_, Error>(outcome.process(m)))
+        .flatten()
+        .map(|m| {
+            let matched = filter.filter(&m);
+            iter_ok::<_, Error>(context.process(m, matched))
+        })
+        .flatten()
+        .map(|m| iter_ok::<_, Error>(dedup.process(m)))
+        .flatten()
+        // Markers bypass the filters
+        .select(markers)
+        .take_while(|m| head(m))
+        .take_while(|m| ok(m.is_some()))
         .forward(output);
 
-    core.run(result).map(|_| 0)
+    // Drop the stream and sink to release the stages
+    let mut result = core.run(result).map(|_| ());
+    if let Some(mut process) = pager_process {
+        // The pager shows the remaining output until it is quit
+        process.wait()?;
+        // Writing fails once the pager is quit
+        if result.as_ref().err().map(is_broken_pipe).unwrap_or(false) {
+            result = Ok(());
+        }
+    }
+    result?;
+    // Wait for trigger commands that are still running
+    core.run(triggers.finish())?;
+    // Keep the pager open until it is closed
+    if let Some(closed) = closed {
+        core.run(closed)?;
+    }
+    Ok(outcome.exit_code())
 }
@@ -0,0 +1,69 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::{err_msg, Error};
+use futures::stream::empty;
+use futures::sync::mpsc;
+use futures::{Future, Stream};
+use record::Record;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use tokio_core::reactor::Handle;
+use RStream;
+
+#[cfg(not(target_os = "windows"))]
+const TTY: &str = "/dev/tty";
+#[cfg(target_os = "windows")]
+const TTY: &str = "CONIN$";
+
+/// Marker records inserted into the output if enabled. Every line entered
+/// on the terminal inserts a marker that is labeled with the line. SIGUSR1
+/// inserts a marker unless the flight recorder uses it to trigger dumps.
+pub fn markers<'a>(args: &ArgMatches<'a>, handle: &Handle) -> Result<RStream, Error> {
+    if !args.is_present("markers") {
+        Ok(Box::new(empty()))
+    } else if args.is_present("flight_recorder") {
+        lines()
+    } else {
+        Ok(Box::new(signal(handle).select(lines()?)))
+    }
+}
+
+/// Lines are read from the tty because stdin might be the input
+fn lines() -> Result<RStream, Error> {
+    let tty = File::open(TTY).map_err(|e| format_err!("Failed to open {}: {}", TTY, e))?;
+    let (tx, rx) = mpsc::unbounded();
+    thread::spawn(move || {
+        for line in BufReader::new(tty).lines() {
+            match line {
+                Ok(line) => if tx.unbounded_send(line).is_err() {
+                    break;
+                },
+                Err(_) => break,
+            }
+        }
+    });
+    let lines = rx.map(|line| Some(Record::marker(line.trim())))
+        .map_err(|_| err_msg("Failed to read from terminal"));
+    Ok(Box::new(lines))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn signal(handle: &Handle) -> RStream {
+    use tokio_signal::unix::{Signal, SIGUSR1};
+    let signal = Signal::new(SIGUSR1, handle)
+        .flatten_stream()
+        .map(|_| Some(Record::marker("SIGUSR1")))
+        .map_err(|e| e.into());
+    Box::new(signal)
+}
+
+#[cfg(target_os = "windows")]
+fn signal(_: &Handle) -> RStream {
+    Box::new(empty())
+}
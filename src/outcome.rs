@@ -0,0 +1,165 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::Error;
+use record::{Level, Record};
+use regex::Regex;
+
+/// Exit code if a --fail-on pattern or level is seen
+pub const EXIT_FAILED: i32 = 2;
+/// Exit code if the run ends before a --exit-on pattern is seen
+pub const EXIT_NOT_FOUND: i32 = 3;
+
+/// Prefix of levels that are abbreviated or could be a pattern as well
+const LEVEL_PREFIX: &str = "level:";
+
+/// Pattern or minimum level passed to --fail-on or --dump-on
+pub enum Condition {
+    Level(Level),
+    Pattern(Regex),
+}
+
+impl Condition {
+    /// Full level names e.g "fatal" and levels prefixed with "level:" e.g
+    /// "level:F" are levels. Anything else is a pattern.
+    pub fn new(c: &str) -> Result<Condition, Error> {
+        if c.starts_with(LEVEL_PREFIX) {
+            let level = &c[LEVEL_PREFIX.len()..];
+            if Level::values().contains(&level) {
+                Ok(Condition::Level(Level::from(level)))
+            } else {
+                Err(format_err!("Invalid level: {}", level))
+            }
+        } else if c.len() > 1 && Level::values().contains(&c) {
+            Ok(Condition::Level(Level::from(c)))
+        } else {
+            Regex::new(c)
+                .map(Condition::Pattern)
+                .map_err(|_| format_err!("Invalid regex string: {}", c))
+        }
+    }
+
+    pub fn is_match(&self, record: &Record) -> bool {
+        match *self {
+            Condition::Level(ref l) => record.level >= *l,
+            Condition::Pattern(ref r) => r.is_match(&record.message),
+        }
+    }
+}
+
+/// Tracks --exit-on and --fail-on conditions and determines the exit code
+pub struct Outcome {
+    exit_on: Vec<Regex>,
+    fail_on: Vec<Condition>,
+    fail_late: bool,
+    exited: bool,
+    failed: bool,
+}
+
+impl<'a> Outcome {
+    pub fn new(args: &ArgMatches<'a>) -> Result<Self, Error> {
+        let regex = |r: &str| Regex::new(r).map_err(|_| format_err!("Invalid regex string: {}", r));
+        let exit_on = args.values_of("exit_on")
+            .map(|v| v.map(|r| regex(r)).collect::<Result<Vec<Regex>, Error>>())
+            .unwrap_or_else(|| Ok(vec![]))?;
+        let fail_on = args.values_of("fail_on")
+            .map(|v| v.map(Condition::new).collect::<Result<Vec<Condition>, Error>>())
+            .unwrap_or_else(|| Ok(vec![]))?;
+
+        Ok(Outcome {
+            exit_on,
+            fail_on,
+            fail_late: args.is_present("fail_late"),
+            exited: false,
+            failed: false,
+        })
+    }
+
+    /// Pass `record` and append a `None` to end the stream if the outcome
+    /// is decided
+    pub fn process(&mut self, record: Option<Record>) -> Vec<Option<Record>> {
+        let done = match record {
+            Some(ref r) => {
+                if self.fail_on.iter().any(|c| c.is_match(r)) {
+                    self.failed = true;
+                }
+                if self.exit_on.iter().any(|e| e.is_match(&r.message)) {
+                    self.exited = true;
+                }
+                self.exited || (self.failed && !self.fail_late)
+            }
+            None => false,
+        };
+
+        if done {
+            vec![record, None]
+        } else {
+            vec![record]
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.failed {
+            EXIT_FAILED
+        } else if !self.exit_on.is_empty() && !self.exited {
+            EXIT_NOT_FOUND
+        } else {
+            0
+        }
+    }
+}
+
+#[test]
+fn condition() {
+    let level = |c: &str| match Condition::new(c).unwrap() {
+        Condition::Level(l) => Some(l),
+        Condition::Pattern(_) => None,
+    };
+    assert_eq!(level("fatal"), Some(Level::Fatal));
+    assert_eq!(level("level:fatal"), Some(Level::Fatal));
+    assert_eq!(level("level:E"), Some(Level::Error));
+    assert_eq!(level("E"), None);
+    assert_eq!(level("FATAL"), None);
+    assert!(Condition::new("level:fat").is_err());
+}
+
+#[test]
+fn outcome() {
+    let record = |level: Level, message: &str| {
+        Some(Record {
+            level,
+            message: message.to_owned(),
+            ..Default::default()
+        })
+    };
+
+    let mut outcome = Outcome {
+        exit_on: vec![Regex::new("boot completed").unwrap()],
+        fail_on: vec![Condition::Level(Level::Fatal)],
+        fail_late: false,
+        exited: false,
+        failed: false,
+    };
+    assert_eq!(outcome.exit_code(), EXIT_NOT_FOUND);
+    assert_eq!(outcome.process(record(Level::Info, "booting")).len(), 1);
+    assert_eq!(outcome.process(record(Level::Info, "boot completed")).len(), 2);
+    assert_eq!(outcome.exit_code(), 0);
+
+    let mut outcome = Outcome {
+        exit_on: vec![],
+        fail_on: vec![
+            Condition::Level(Level::Fatal),
+            Condition::Pattern(Regex::new("^FATAL EXCEPTION").unwrap()),
+        ],
+        fail_late: true,
+        exited: false,
+        failed: false,
+    };
+    assert_eq!(outcome.exit_code(), 0);
+    assert_eq!(outcome.process(record(Level::Error, "FATAL EXCEPTION: main")).len(), 1);
+    assert_eq!(outcome.exit_code(), EXIT_FAILED);
+}
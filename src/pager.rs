@@ -0,0 +1,462 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::{err_msg, Error};
+use filter::Filter;
+use futures::future::Shared;
+use futures::sync::{mpsc, oneshot};
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use profiles::Profile;
+use RFuture;
+use record::{Level, Record};
+use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{stdout, Stdout, Write};
+use std::rc::Rc;
+use std::thread;
+use terminal::Terminal;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, get_tty, style, terminal_size};
+use tokio_core::reactor::Handle;
+
+/// Maximum number of records kept for scrolling
+const SCROLLBACK: usize = 100_000;
+
+const HELP: &str = "q:quit f:follow /:search n/N:next/prev e/E:error &:filter";
+
+lazy_static! {
+    static ref ESCAPE: Regex = Regex::new(r"\x1b(?:\[[0-9;?]*[A-Za-z]|\([0-9A-Z])").unwrap();
+}
+
+/// Highlight matches of `search` outside of escape sequences
+fn highlight(search: &Regex, text: &str) -> String {
+    let mark = |t: &str, result: &mut String| {
+        result.push_str(&search.replace_all(t, |c: &Captures| {
+            format!("{}{}{}", style::Invert, &c[0], style::NoInvert)
+        }));
+    };
+    let mut result = String::new();
+    let mut last = 0;
+    for m in ESCAPE.find_iter(text) {
+        mark(&text[last..m.start()], &mut result);
+        result.push_str(m.as_str());
+        last = m.end();
+    }
+    mark(&text[last..], &mut result);
+    result
+}
+
+/// Line editing in the status line
+enum Input {
+    None,
+    Search(String),
+    Filter(String),
+}
+
+/// Rendered line of a record
+struct Line {
+    record: usize,
+    text: String,
+}
+
+impl Line {
+    /// Text without escape sequences
+    fn plain(&self) -> String {
+        ESCAPE.replace_all(&self.text, "").to_string()
+    }
+}
+
+struct State {
+    /// Number of records removed from the scrollback
+    dropped: usize,
+    filter: Option<Filter>,
+    filter_string: String,
+    follow: bool,
+    input: Input,
+    lines: VecDeque<Line>,
+    records: VecDeque<Record>,
+    renderer: Terminal<Vec<u8>>,
+    screen: AlternateScreen<RawTerminal<Stdout>>,
+    search: Option<Regex>,
+    search_string: String,
+    size: (u16, u16),
+    /// Index of the first visible line
+    top: usize,
+}
+
+impl State {
+    fn rows(&self) -> usize {
+        (self.size.1 as usize).saturating_sub(1).max(1)
+    }
+
+    fn bottom(&self) -> usize {
+        self.lines.len().saturating_sub(self.rows())
+    }
+
+    fn record(&self, line: &Line) -> &Record {
+        &self.records[line.record - self.dropped]
+    }
+
+    fn render(&mut self, index: usize) -> Result<(), Error> {
+        let record = &self.records[index - self.dropped];
+        if self.filter
+            .as_mut()
+            .map(|f| f.matches(record))
+            .unwrap_or(true)
+        {
+            for text in self.renderer.render(record)? {
+                self.lines.push_back(Line {
+                    record: index,
+                    text,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, record: Record) -> Result<(), Error> {
+        self.records.push_back(record);
+        if self.records.len() > SCROLLBACK {
+            self.records.pop_front();
+            self.dropped += 1;
+            while self.lines
+                .front()
+                .map(|l| l.record < self.dropped)
+                .unwrap_or(false)
+            {
+                self.lines.pop_front();
+                self.top = self.top.saturating_sub(1);
+            }
+        }
+        let index = self.dropped + self.records.len() - 1;
+        self.render(index)
+    }
+
+    /// Render all records again e.g after the filter or size changed
+    fn rebuild(&mut self) -> Result<(), Error> {
+        let record = self.lines.get(self.top).map(|l| l.record);
+        self.lines.clear();
+        for index in self.dropped..self.dropped + self.records.len() {
+            self.render(index)?;
+        }
+        // Try to keep the first visible record
+        self.top = record
+            .and_then(|r| self.lines.iter().position(|l| l.record >= r))
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    fn scroll(&mut self, n: isize) {
+        self.follow = false;
+        let top = self.top as isize + n;
+        self.top = (top.max(0) as usize).min(self.bottom());
+    }
+
+    /// Scroll to the next line after (or before) the first visible one
+    /// that satisfies `f`
+    fn find<F: Fn(&State, usize) -> bool>(&mut self, forward: bool, f: F) {
+        let found = if forward {
+            (self.top + 1..self.lines.len()).find(|i| f(self, *i))
+        } else {
+            (0..self.top).rev().find(|i| f(self, *i))
+        };
+        if let Some(i) = found {
+            self.follow = false;
+            self.top = i.min(self.bottom());
+        }
+    }
+
+    fn find_error(&mut self, forward: bool) {
+        self.find(forward, |s, i| {
+            let line = &s.lines[i];
+            // Stop at the first line of wrapped records only
+            let first = i == 0 || s.lines[i - 1].record != line.record;
+            first && s.record(line).level >= Level::Error
+        });
+    }
+
+    fn find_match(&mut self, forward: bool) {
+        if let Some(search) = self.search.clone() {
+            self.find(forward, |s, i| search.is_match(&s.lines[i].plain()));
+        }
+    }
+
+    fn status(&self) -> String {
+        match self.input {
+            Input::Search(ref s) => format!("/{}", s),
+            Input::Filter(ref s) => format!("&{}", s),
+            Input::None => {
+                let mut status = vec![
+                    if self.follow { "FOLLOW" } else { "PAUSED" }.to_owned(),
+                    format!("{} records", self.dropped + self.records.len()),
+                ];
+                if !self.filter_string.is_empty() {
+                    status.push(format!("filter: {}", self.filter_string));
+                }
+                if !self.search_string.is_empty() {
+                    status.push(format!("search: {}", self.search_string));
+                }
+                status.push(HELP.to_owned());
+                status.join(" | ")
+            }
+        }
+    }
+
+    fn draw(&mut self) -> Result<(), Error> {
+        let size = terminal_size()?;
+        if size != self.size {
+            self.size = size;
+            self.rebuild()?;
+        }
+        if self.follow {
+            self.top = self.bottom();
+        }
+
+        let mut output = format!("{}{}", cursor::Hide, clear::All);
+        let lines = self.lines.iter().skip(self.top).take(self.rows());
+        for (row, line) in lines.enumerate() {
+            output.push_str(&format!(
+                "{}{}{}",
+                cursor::Goto(1, row as u16 + 1),
+                self.search
+                    .as_ref()
+                    .map(|s| highlight(s, &line.text))
+                    .unwrap_or_else(|| line.text.clone()),
+                style::Reset
+            ));
+        }
+        let status = self.status()
+            .chars()
+            .take(self.size.0 as usize)
+            .collect::<String>();
+        output.push_str(&format!(
+            "{}{}{}{}",
+            cursor::Goto(1, self.size.1),
+            style::Invert,
+            status,
+            style::Reset
+        ));
+        // Show the cursor while editing the search or filter
+        match self.input {
+            Input::None => (),
+            _ => output.push_str(&format!("{}", cursor::Show)),
+        }
+        self.screen.write_all(output.as_bytes())?;
+        self.screen.flush()?;
+        Ok(())
+    }
+
+    /// Process a key in line editing mode and return the edited line
+    /// on enter
+    fn edit(line: &mut String, key: Key) -> Option<Option<String>> {
+        match key {
+            Key::Char('\n') => Some(Some(line.clone())),
+            Key::Esc => Some(None),
+            Key::Backspace => {
+                line.pop();
+                None
+            }
+            Key::Char(c) => {
+                line.push(c);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn set_search(&mut self, search: &str) {
+        self.search_string = search.to_owned();
+        self.search = if search.is_empty() {
+            None
+        } else {
+            Regex::new(search).ok()
+        };
+    }
+
+    /// Set the tag, message and level filter. See `Filter::live`.
+    fn set_filter(&mut self, filter: &str) -> Result<(), Error> {
+        self.filter = if filter.trim().is_empty() {
+            None
+        } else {
+            Some(Filter::live(filter)?)
+        };
+        self.filter_string = filter.to_owned();
+        self.rebuild()
+    }
+
+    /// Process `key` and return false if the pager is closed
+    fn key(&mut self, key: Key) -> Result<bool, Error> {
+        let rows = self.rows() as isize;
+        let input = ::std::mem::replace(&mut self.input, Input::None);
+        match input {
+            Input::Search(mut s) => match Self::edit(&mut s, key) {
+                Some(Some(s)) => self.set_search(&s),
+                Some(None) => self.set_search(""),
+                None => {
+                    // Incremental search from the first visible line
+                    self.set_search(&s);
+                    let visible = self.search
+                        .as_ref()
+                        .and_then(|r| self.lines.get(self.top).map(|l| r.is_match(&l.plain())))
+                        .unwrap_or(true);
+                    if !visible {
+                        self.find_match(true);
+                    }
+                    self.input = Input::Search(s);
+                }
+            },
+            Input::Filter(mut s) => match Self::edit(&mut s, key) {
+                Some(Some(s)) => {
+                    // Keep editing if the filter is invalid
+                    if self.set_filter(&s).is_err() {
+                        self.input = Input::Filter(s);
+                    }
+                }
+                Some(None) => (),
+                None => self.input = Input::Filter(s),
+            },
+            Input::None => match key {
+                Key::Char('q') | Key::Ctrl('c') => return Ok(false),
+                Key::Up | Key::Char('k') => self.scroll(-1),
+                Key::Down | Key::Char('j') | Key::Char('\n') => self.scroll(1),
+                Key::PageUp | Key::Char('b') => self.scroll(-rows),
+                Key::PageDown | Key::Char(' ') => self.scroll(rows),
+                Key::Home | Key::Char('g') => {
+                    self.follow = false;
+                    self.top = 0;
+                }
+                Key::End | Key::Char('G') => self.follow = true,
+                Key::Char('f') => self.follow = !self.follow,
+                Key::Char('/') => self.input = Input::Search(String::new()),
+                Key::Char('n') => self.find_match(true),
+                Key::Char('N') => self.find_match(false),
+                Key::Char('e') => self.find_error(true),
+                Key::Char('E') => self.find_error(false),
+                Key::Char('&') => self.input = Input::Filter(self.filter_string.clone()),
+                _ => (),
+            },
+        }
+        self.draw()?;
+        Ok(true)
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        write!(self.screen, "{}", cursor::Show).ok();
+        self.screen.flush().ok();
+    }
+}
+
+/// Interactive full screen output with scrollback, search and filtering
+pub struct Pager {
+    dirty: bool,
+    quit: Shared<oneshot::Receiver<()>>,
+    state: Rc<RefCell<State>>,
+}
+
+impl<'a> Pager {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile, handle: &Handle) -> Result<Self, Error> {
+        let tty = get_tty().map_err(|e| format_err!("Failed to open tty: {}", e))?;
+        let renderer = Terminal::buffered(args, profile)?;
+        let screen = AlternateScreen::from(stdout().into_raw_mode()?);
+        let state = Rc::new(RefCell::new(State {
+            dropped: 0,
+            filter: None,
+            filter_string: String::new(),
+            follow: true,
+            input: Input::None,
+            lines: VecDeque::new(),
+            records: VecDeque::new(),
+            renderer,
+            screen,
+            search: None,
+            search_string: String::new(),
+            size: terminal_size()?,
+            top: 0,
+        }));
+        state.borrow_mut().draw()?;
+
+        // Keys are read from the tty because stdin might be the input
+        let (tx, rx) = mpsc::unbounded();
+        thread::spawn(move || {
+            for key in tty.keys() {
+                match key {
+                    Ok(key) => if tx.unbounded_send(key).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let (quit_tx, quit_rx) = oneshot::channel();
+        let keys_state = state.clone();
+        let keys = rx.for_each(move |key| match keys_state.borrow_mut().key(key) {
+            Ok(true) => Ok(()),
+            _ => Err(()),
+        }).then(|_| quit_tx.send(()));
+        handle.spawn(keys);
+
+        Ok(Pager {
+            dirty: false,
+            quit: quit_rx.shared(),
+            state,
+        })
+    }
+
+    /// Future that resolves when the pager is closed
+    pub fn closed(&self) -> RFuture {
+        Box::new(
+            self.quit
+                .clone()
+                .map(|_| ())
+                .map_err(|_| err_msg("Pager terminated")),
+        )
+    }
+}
+
+impl Sink for Pager {
+    type SinkItem = Option<Record>;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if let Some(record) = item {
+            self.state.borrow_mut().add(record)?;
+            self.dirty = true;
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        // Redraw once the input is idle
+        if self.dirty {
+            self.state.borrow_mut().draw()?;
+            self.dirty = false;
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+#[test]
+fn highlight_matches() {
+    let search = Regex::new("3").unwrap();
+    let line = "\x1b[38;5;3mA3\x1b[0m 3";
+    assert_eq!(
+        highlight(&search, line),
+        "\x1b[38;5;3mA\x1b[7m3\x1b[27m\x1b[0m \x1b[7m3\x1b[27m"
+    );
+    let line = Line {
+        record: 0,
+        text: line.to_owned(),
+    };
+    assert_eq!(line.plain(), "A3 3");
+}
@@ -35,7 +35,7 @@ named!(
 );
 
 named!(
-    timestamp<Tm>,
+    timestamp<Timestamp>,
     do_parse!(
         year:
             opt!(do_parse!(
@@ -52,18 +52,21 @@ named!(
                     }) >> utc_off_hours: flat_map!(take!(2), num_i32)
                         >> utc_off_minutes: flat_map!(take!(2), num_i32)
                         >> (sign * (utc_off_hours * 60 * 60 + utc_off_minutes * 60))
-                ))) >> (Tm {
-            tm_sec: second,
-            tm_min: minute,
-            tm_hour: hour,
-            tm_mday: day,
-            tm_mon: month - 1,
-            tm_year: year.unwrap_or(0),
-            tm_wday: 0,
-            tm_yday: 0,
-            tm_isdst: 0,
-            tm_utcoff: utcoff.unwrap_or(0),
-            tm_nsec: millisecond * 1_000_000,
+                ))) >> (Timestamp {
+            tm: Tm {
+                tm_sec: second,
+                tm_min: minute,
+                tm_hour: hour,
+                tm_mday: day,
+                tm_mon: month - 1,
+                tm_year: year.unwrap_or(0),
+                tm_wday: 0,
+                tm_yday: 0,
+                tm_isdst: 0,
+                tm_utcoff: utcoff.unwrap_or(0),
+                tm_nsec: millisecond * 1_000_000,
+            },
+            utc_offset: utcoff,
         })
     )
 );
@@ -75,7 +78,7 @@ named!(
             >> many1!(space) >> thread: map_res!(hex_digit, from_utf8) >> many1!(space)
             >> level: level >> space >> tag: map_res!(take_until!(":"), from_utf8)
             >> char!(':') >> message: opt!(map_res!(rest, from_utf8)) >> (Record {
-            timestamp: Some(Timestamp::new(timestamp)),
+            timestamp: Some(timestamp),
             level: level,
             tag: tag.trim().to_owned(),
             process: process.trim().to_owned(),
@@ -122,7 +125,7 @@ named!(
             message: opt!(map_res!(rest, from_utf8)) >>
             (
                 Record {
-                    timestamp: Some(Timestamp::new(timestamp)),
+                    timestamp: Some(timestamp),
                     level: level,
                     tag: tag.trim().to_owned(),
                     process: process.trim().to_owned(),
@@ -191,8 +194,13 @@ impl Parser {
         let mut rdr = ReaderBuilder::new()
             .has_headers(false)
             .from_reader(line.as_bytes());
-        if let Some(result) = rdr.deserialize().next() {
-            return result.map_err(|e| e.into());
+        if let Some(result) = rdr.records().next() {
+            let mut record = result?;
+            // The context column is only written with context records
+            if record.len() == 7 {
+                record.push_field("");
+            }
+            return record.deserialize(None).map_err(|e| e.into());
         }
         Err(err_msg("Failed to parse csv"))
     }
@@ -353,6 +361,21 @@ fn parse_printable() {
     assert_eq!(r.message, "");
 }
 
+#[test]
+fn parse_utc_offset() {
+    let t = "2018-01-01 12:00:00.250 +0000     1     2 I A: a";
+    let r = Parser::parse_default(t).unwrap();
+    assert_eq!(r.timestamp.unwrap().utc_offset, Some(0));
+
+    let t = "2018-01-01 12:00:00.250 -0130     1     2 I A: a";
+    let r = Parser::parse_default(t).unwrap();
+    assert_eq!(r.timestamp.unwrap().utc_offset, Some(-5400));
+
+    let t = "2018-01-01 12:00:00.250     1     2 I A: a";
+    let r = Parser::parse_default(t).unwrap();
+    assert_eq!(r.timestamp.unwrap().utc_offset, None);
+}
+
 #[test]
 fn parse_mindroid() {
     let t = "D/ServiceManager(123): Service MediaPlayer has been created in process main";
@@ -384,6 +407,52 @@ fn parse_mindroid() {
     assert_eq!(r.message, "");
 }
 
+#[test]
+fn parse_marker() {
+    let marker = Record::marker("button");
+    assert!(marker.is_marker());
+    let r = Parser::parse_default(&marker.raw).unwrap();
+    assert!(r.is_marker());
+    assert_eq!(r.message, "button");
+    assert_eq!(r.timestamp.unwrap().tm_min, marker.timestamp.unwrap().tm_min);
+}
+
+#[test]
+fn logcat_formats() {
+    use record::Format;
+    let t = "07-01 14:13:14.446   225   295 I ThermalEngine: Sensor:batt_therm:29000 mC";
+    let r = Parser::parse_default(t).unwrap();
+    let format = |f: Format| r.format(&f).unwrap();
+    assert_eq!(format(Format::Threadtime), t);
+    assert_eq!(format(Format::Brief), "I/ThermalEngine(  225): Sensor:batt_therm:29000 mC");
+    assert_eq!(format(Format::Process), "I(  225) Sensor:batt_therm:29000 mC  (ThermalEngine)");
+    assert_eq!(format(Format::Tag), "I/ThermalEngine: Sensor:batt_therm:29000 mC");
+    assert_eq!(format(Format::Thread), "I(  225:  295) Sensor:batt_therm:29000 mC");
+    assert_eq!(
+        format(Format::Time),
+        "07-01 14:13:14.446 I/ThermalEngine(  225): Sensor:batt_therm:29000 mC"
+    );
+    assert_eq!(
+        format(Format::Long),
+        "[ 07-01 14:13:14.446   225:  295 I/ThermalEngine ]\nSensor:batt_therm:29000 mC\n"
+    );
+    assert_eq!(
+        r.format_with_timestamp(&Format::Time, "1.250").unwrap(),
+        "1.250 I/ThermalEngine(  225): Sensor:batt_therm:29000 mC"
+    );
+    // Unparsed lines are passed
+    let r = Record {
+        message: "--------- beginning of main".to_owned(),
+        raw: "--------- beginning of main".to_owned(),
+        ..Default::default()
+    };
+    assert_eq!(r.format(&Format::Threadtime).unwrap(), "--------- beginning of main");
+    // Formats that are rendered by the terminal or a file writer
+    assert!(r.format(&Format::Human).is_err());
+    assert!(r.format(&Format::Template).is_err());
+    assert!(r.format_with_timestamp(&Format::Html, "1.250").is_err());
+}
+
 #[test]
 fn parse_csv_unparseable() {
     assert!(Parser::parse_csv("").is_err());
@@ -405,6 +474,36 @@ fn parse_csv() {
     );
 }
 
+#[test]
+fn parse_csv_context() {
+    let t = "07-01 14:13:14.446000000,Sensor:batt_therm:29000 mC,Info,ThermalEngine,225,295,raw,true";
+    let r = Parser::parse_csv(t).unwrap();
+    assert!(r.is_context());
+    assert_eq!(r.raw, "raw");
+
+    let r = Record {
+        message: "A".to_owned(),
+        context: Some(true),
+        ..Default::default()
+    };
+    let csv = r.format(&::record::Format::Csv).unwrap();
+    assert_eq!(Parser::parse_csv(&csv).unwrap(), r);
+    let json = r.format(&::record::Format::Json).unwrap();
+    assert_eq!(Parser::parse_json(&json).unwrap(), r);
+
+    // Without context options the field is not written
+    let r = Record {
+        message: "A".to_owned(),
+        ..Default::default()
+    };
+    let csv = r.format(&::record::Format::Csv).unwrap();
+    assert_eq!(csv.split(',').count(), 7);
+    assert_eq!(Parser::parse_csv(&csv).unwrap(), r);
+    let json = r.format(&::record::Format::Json).unwrap();
+    assert!(!json.contains("context"));
+    assert_eq!(Parser::parse_json(&json).unwrap(), r);
+}
+
 #[test]
 fn parse_property() {
     let t = "[ro.build.tags]: [release-keys]";
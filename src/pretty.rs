@@ -0,0 +1,242 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use regex::Regex;
+use serde_json::{from_str, Value};
+
+/// Indentation of nested JSON values
+const INDENT: &str = "  ";
+
+lazy_static! {
+    static ref KV: Regex = Regex::new(r#"^\s*(?:[\w.-]+=(?:"[^"]*"|[^\s,"]*)[\s,]*){2,}$"#).unwrap();
+    static ref KV_PAIR: Regex = Regex::new(r#"([\w.-]+)=("[^"]*"|[^\s,"]*)"#).unwrap();
+}
+
+/// Syntax element of a pretty printed message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    Key,
+    Literal,
+    Number,
+    Punctuation,
+    String,
+    Text,
+}
+
+pub type Line = Vec<(String, Syntax)>;
+
+/// Payload formats that are pretty printed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pretty {
+    pub json: bool,
+    pub kv: bool,
+}
+
+impl Pretty {
+    pub fn is_enabled(&self) -> bool {
+        self.json || self.kv
+    }
+
+    /// Lines of the pretty printed `message` or None if it does not contain
+    /// a JSON object or array or is not a list of key=value pairs
+    pub fn format(&self, message: &str) -> Option<Vec<Line>> {
+        if self.json {
+            if let Some(lines) = json(message) {
+                return Some(lines);
+            }
+        }
+        if self.kv {
+            if let Some(lines) = kv(message) {
+                return Some(lines);
+            }
+        }
+        None
+    }
+}
+
+/// Find a JSON object or array that spans until the end of `message`
+fn json(message: &str) -> Option<Vec<Line>> {
+    let trimmed = message.trim_right();
+    if !trimmed.ends_with('}') && !trimmed.ends_with(']') {
+        return None;
+    }
+    let start = trimmed
+        .char_indices()
+        .filter(|&(_, c)| c == '{' || c == '[')
+        .map(|(i, _)| i)
+        .find(|i| match from_str::<Value>(&trimmed[*i..]) {
+            Ok(Value::Object(_)) | Ok(Value::Array(_)) => true,
+            _ => false,
+        })?;
+
+    let mut lines = vec![];
+    let prefix = trimmed[..start].trim_right();
+    if !prefix.is_empty() {
+        lines.push(vec![(prefix.to_owned(), Syntax::Text)]);
+    }
+
+    let mut line: Line = vec![];
+    let mut depth = 0;
+    let mut chars = trimmed[start..].chars().peekable();
+    let newline = |line: &mut Line, lines: &mut Vec<Line>, depth: usize| {
+        lines.push(line.split_off(0));
+        if depth > 0 {
+            line.push((INDENT.repeat(depth), Syntax::Text));
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '[' => {
+                // Keep empty objects and arrays on one line
+                while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                    chars.next();
+                }
+                let close = if c == '{' { '}' } else { ']' };
+                if chars.peek() == Some(&close) {
+                    chars.next();
+                    line.push((format!("{}{}", c, close), Syntax::Punctuation));
+                } else {
+                    line.push((c.to_string(), Syntax::Punctuation));
+                    depth += 1;
+                    newline(&mut line, &mut lines, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut line, &mut lines, depth);
+                line.push((c.to_string(), Syntax::Punctuation));
+            }
+            ',' => {
+                line.push((",".to_owned(), Syntax::Punctuation));
+                newline(&mut line, &mut lines, depth);
+            }
+            ':' => line.push((": ".to_owned(), Syntax::Punctuation)),
+            '"' => {
+                let mut s = String::from("\"");
+                while let Some(c) = chars.next() {
+                    s.push(c);
+                    if c == '\\' {
+                        s.extend(chars.next());
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+                // Strings followed by a colon are keys
+                while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                    chars.next();
+                }
+                let syntax = if chars.peek() == Some(&':') {
+                    Syntax::Key
+                } else {
+                    Syntax::String
+                };
+                line.push((s, syntax));
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut s = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || ",:]}".contains(c) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                let syntax = if c == '-' || c.is_digit(10) {
+                    Syntax::Number
+                } else {
+                    Syntax::Literal
+                };
+                line.push((s, syntax));
+            }
+        }
+    }
+    lines.push(line);
+    Some(lines)
+}
+
+/// Split a list of key=value pairs into one line per pair
+fn kv(message: &str) -> Option<Vec<Line>> {
+    if !KV.is_match(message) {
+        return None;
+    }
+    Some(
+        KV_PAIR
+            .captures_iter(message)
+            .map(|c| {
+                let value = &c[2];
+                let syntax = if value.starts_with('"') {
+                    Syntax::String
+                } else if value.parse::<f64>().is_ok() {
+                    Syntax::Number
+                } else {
+                    Syntax::Literal
+                };
+                vec![
+                    (c[1].to_owned(), Syntax::Key),
+                    ("=".to_owned(), Syntax::Punctuation),
+                    (value.to_owned(), syntax),
+                ]
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+fn text(lines: &[Line]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|l| l.iter().map(|t| t.0.as_str()).collect::<String>())
+        .collect()
+}
+
+#[test]
+fn pretty_json() {
+    let pretty = Pretty {
+        json: true,
+        kv: false,
+    };
+    let lines = pretty
+        .format(r#"Response: {"b": [1, {"c": null}], "a": "x,{y}", "e": {}}"#)
+        .unwrap();
+    assert_eq!(
+        text(&lines),
+        vec![
+            "Response:",
+            "{",
+            "  \"b\": [",
+            "    1,",
+            "    {",
+            "      \"c\": null",
+            "    }",
+            "  ],",
+            "  \"a\": \"x,{y}\",",
+            "  \"e\": {}",
+            "}",
+        ]
+    );
+    assert_eq!(lines[2][1], ("\"b\"".to_owned(), Syntax::Key));
+    assert_eq!(lines[3][1], ("1".to_owned(), Syntax::Number));
+    assert_eq!(lines[8][3], ("\"x,{y}\"".to_owned(), Syntax::String));
+
+    assert!(pretty.format("no json {").is_none());
+    assert!(pretty.format("\"string\"").is_none());
+    assert!(pretty.format("a=1 b=2").is_none());
+    assert_eq!(text(&pretty.format(r#"["a\"]"]"#).unwrap()), vec!["[", "  \"a\\\"]\"", "]"]);
+}
+
+#[test]
+fn pretty_kv() {
+    let pretty = Pretty {
+        json: false,
+        kv: true,
+    };
+    let lines = pretty.format(r#"user=foo id=3, name="a b""#).unwrap();
+    assert_eq!(text(&lines), vec!["user=foo", "id=3", "name=\"a b\""]);
+    assert_eq!(lines[1][2], ("3".to_owned(), Syntax::Number));
+    assert!(pretty.format("a=1").is_none());
+    assert!(pretty.format("a=1 and b=2").is_none());
+}
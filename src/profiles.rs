@@ -14,6 +14,7 @@ use std::ops::AddAssign;
 use std::path::PathBuf;
 use std::convert::Into;
 use toml::{from_str, to_string};
+use trigger::TriggerSpec;
 
 const EXTEND_LIMIT: u32 = 1000;
 
@@ -21,19 +22,35 @@ const EXTEND_LIMIT: u32 = 1000;
 pub struct ProfileFile {
     extends: Option<Vec<String>>,
     comment: Option<String>,
+    dedup: Option<bool>,
+    filterspec: Option<Vec<String>>,
+    fixed_strings: Option<Vec<String>>,
     highlight: Option<Vec<String>>,
+    ignore_case: Option<Vec<String>>,
+    layout: Option<String>,
     message: Option<Vec<String>>,
+    rate_limit: Option<u32>,
     tag: Option<Vec<String>>,
+    template: Option<String>,
+    trigger: Option<Vec<TriggerSpec>>,
 }
 
 impl Into<Profile> for ProfileFile {
     fn into(self) -> Profile {
         Profile {
             comment: self.comment,
+            dedup: self.dedup.unwrap_or(false),
             extends: self.extends.unwrap_or_else(|| vec![]),
+            filterspec: self.filterspec.unwrap_or_else(|| vec![]),
+            fixed_strings: self.fixed_strings.unwrap_or_else(|| vec![]),
             highlight: self.highlight.unwrap_or_else(|| vec![]),
+            ignore_case: self.ignore_case.unwrap_or_else(|| vec![]),
+            layout: self.layout,
             message: self.message.unwrap_or_else(|| vec![]),
+            rate_limit: self.rate_limit,
             tag: self.tag.unwrap_or_else(|| vec![]),
+            template: self.template,
+            trigger: self.trigger.unwrap_or_else(|| vec![]),
         }
     }
 }
@@ -46,10 +63,18 @@ struct ConfigurationFile {
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Profile {
     comment: Option<String>,
+    dedup: bool,
     extends: Vec<String>,
+    filterspec: Vec<String>,
+    fixed_strings: Vec<String>,
     highlight: Vec<String>,
+    ignore_case: Vec<String>,
+    layout: Option<String>,
     message: Vec<String>,
+    rate_limit: Option<u32>,
     tag: Vec<String>,
+    template: Option<String>,
+    trigger: Vec<TriggerSpec>,
 }
 
 impl Profile {
@@ -57,17 +82,51 @@ impl Profile {
         &self.comment
     }
 
+    pub fn dedup(&self) -> bool {
+        self.dedup
+    }
+
+    pub fn filterspec(&self) -> &Vec<String> {
+        &self.filterspec
+    }
+
+    /// Kinds of filters ("tag" or "message") that are plain strings
+    pub fn fixed_strings(&self) -> &Vec<String> {
+        &self.fixed_strings
+    }
+
     pub fn highlight(&self) -> &Vec<String> {
         &self.highlight
     }
 
+    /// Kinds of filters ("tag" or "message") that match case insensitive
+    pub fn ignore_case(&self) -> &Vec<String> {
+        &self.ignore_case
+    }
+
+    pub fn layout(&self) -> &Option<String> {
+        &self.layout
+    }
+
     pub fn message(&self) -> &Vec<String> {
         &self.message
     }
 
+    pub fn rate_limit(&self) -> &Option<u32> {
+        &self.rate_limit
+    }
+
     pub fn tag(&self) -> &Vec<String> {
         &self.tag
     }
+
+    pub fn template(&self) -> &Option<String> {
+        &self.template
+    }
+
+    pub fn trigger(&self) -> &Vec<TriggerSpec> {
+        &self.trigger
+    }
 }
 
 impl AddAssign for Profile {
@@ -81,9 +140,18 @@ impl AddAssign for Profile {
         }
 
         vec_extend!(self.extends, other.extends);
+        vec_extend!(self.filterspec, other.filterspec);
+        vec_extend!(self.fixed_strings, other.fixed_strings);
         vec_extend!(self.highlight, other.highlight);
+        vec_extend!(self.ignore_case, other.ignore_case);
         vec_extend!(self.message, other.message);
         vec_extend!(self.tag, other.tag);
+        vec_extend!(self.trigger, other.trigger);
+        self.dedup |= other.dedup;
+        // Settings of the extending profile take precedence
+        self.layout = self.layout.take().or(other.layout);
+        self.rate_limit = self.rate_limit.or(other.rate_limit);
+        self.template = self.template.take().or(other.template);
     }
 }
 
@@ -124,6 +192,14 @@ impl Profiles {
                     .ok_or_else(|| format_err!("Unknown profile {}", n))?
                     .clone();
                 Self::expand(n, &mut profile, &profiles)?;
+                if let Some(k) = profile
+                    .fixed_strings
+                    .iter()
+                    .chain(&profile.ignore_case)
+                    .find(|k| *k != "tag" && *k != "message")
+                {
+                    return Err(format_err!("Invalid filter kind {} in profile {}", k, n));
+                }
             }
 
             Ok(Profiles {
@@ -236,6 +312,66 @@ impl Profiles {
                 },
             );
 
+            example.profile.insert(
+                "logcat".into(),
+                ProfileFile {
+                    comment: Some("ActivityManager from info and MyApp with all levels".into()),
+                    filterspec: Some(vec!["ActivityManager:I".into(), "MyApp:V".into(), "*:S".into()]),
+                    ..Default::default()
+                },
+            );
+
+            example.profile.insert(
+                "quiet".into(),
+                ProfileFile {
+                    comment: Some(
+                        "Collapse repeated messages and allow 10 records per second and tag".into(),
+                    ),
+                    dedup: Some(true),
+                    rate_limit: Some(10),
+                    ..Default::default()
+                },
+            );
+
+            example.profile.insert(
+                "activity".into(),
+                ProfileFile {
+                    comment: Some("Tag in any case and the component name as plain string".into()),
+                    fixed_strings: Some(vec!["message".into()]),
+                    ignore_case: Some(vec!["tag".into()]),
+                    message: Some(vec!["cmp=com.example/.Main".into()]),
+                    tag: Some(vec!["activitymanager".into()]),
+                    ..Default::default()
+                },
+            );
+
+            example.profile.insert(
+                "narrow".into(),
+                ProfileFile {
+                    comment: Some("Compact columns for small terminals".into()),
+                    layout: Some("{tag:>12:ellipsis} {level} {message}".into()),
+                    ..Default::default()
+                },
+            );
+
+            example.profile.insert(
+                "crash".into(),
+                ProfileFile {
+                    comment: Some(
+                        "Grab a screenshot when an app crashes. Fields are passed as ROGCAT_<FIELD>"
+                            .into(),
+                    ),
+                    trigger: Some(vec![TriggerSpec {
+                        command: "adb exec-out screencap -p > crash-{{process}}.png".into(),
+                        interval: Some("1m".into()),
+                        level: Some("error".into()),
+                        message: Some("^FATAL EXCEPTION".into()),
+                        tag: Some("^AndroidRuntime$".into()),
+                    }]),
+                    ..Default::default()
+                },
+            );
+
             example.profile.insert(
                 "complex".into(),
                 ProfileFile {
@@ -12,28 +12,67 @@ use serde::Serialize;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use std::ops::Deref;
-use time::{strftime, strptime, Tm};
+use time::{self, now, strptime, Tm};
+use timestamps::strftime;
 
 type StdResult<T, E> = ::std::result::Result<T, E>;
 
+/// Format of timestamps in CSV and JSON
+pub const TIMESTAMP_FORMAT: &str = "%m-%d %H:%M:%S.%f";
+
+/// Format of timestamps in the logcat formats
+pub const LOGCAT_TIMESTAMP_FORMAT: &str = "%m-%d %H:%M:%S.%3f";
+
+/// Tag of marker records
+const MARKER_TAG: &str = "marker";
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Format {
+    /// logcat -v brief
+    Brief,
     Csv,
     Html,
     Human,
     Json,
+    /// logcat -v long
+    Long,
+    /// logcat -v process
+    Process,
     Raw,
+    /// logcat -v tag
+    Tag,
+    /// User defined handlebars template
+    Template,
+    /// logcat -v thread
+    Thread,
+    /// logcat -v threadtime
+    Threadtime,
+    /// logcat -v time
+    Time,
 }
 
+pub const FORMATS: &[&str] = &[
+    "brief", "csv", "html", "human", "json", "long", "process", "raw", "tag", "template", "thread", "threadtime",
+    "time",
+];
+
 impl FromStr for Format {
     type Err = &'static str;
     fn from_str(s: &str) -> StdResult<Self, Self::Err> {
         match s {
+            "brief" => Ok(Format::Brief),
             "csv" => Ok(Format::Csv),
             "html" => Ok(Format::Html),
             "human" => Ok(Format::Human),
             "json" => Ok(Format::Json),
+            "long" => Ok(Format::Long),
+            "process" => Ok(Format::Process),
             "raw" => Ok(Format::Raw),
+            "tag" => Ok(Format::Tag),
+            "template" => Ok(Format::Template),
+            "thread" => Ok(Format::Thread),
+            "threadtime" => Ok(Format::Threadtime),
+            "time" => Ok(Format::Time),
             _ => Err("Format parsing error"),
         }
     }
@@ -45,16 +84,34 @@ impl Display for Format {
             f,
             "{}",
             match *self {
+                Format::Brief => "brief",
                 Format::Csv => "csv",
                 Format::Html => "html",
                 Format::Human => "human",
                 Format::Json => "json",
+                Format::Long => "long",
+                Format::Process => "process",
                 Format::Raw => "raw",
+                Format::Tag => "tag",
+                Format::Template => "template",
+                Format::Thread => "thread",
+                Format::Threadtime => "threadtime",
+                Format::Time => "time",
             }
         )
     }
 }
 
+impl Format {
+    /// Format of the timestamps passed to `Record::format_with_timestamp`
+    pub fn timestamp_format(&self) -> &'static str {
+        match *self {
+            Format::Long | Format::Threadtime | Format::Time => LOGCAT_TIMESTAMP_FORMAT,
+            _ => TIMESTAMP_FORMAT,
+        }
+    }
+}
+
 const LEVEL_VALUES: &[&str] = &[
     "trace", "debug", "info", "warn", "error", "fatal", "assert", "T", "D", "I", "W", "E", "F", "A"
 ];
@@ -123,6 +180,8 @@ impl Level {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Timestamp {
     pub tm: Tm,
+    /// Offset to UTC in seconds if known e.g from `logcat -v zone`
+    pub utc_offset: Option<i32>,
 }
 
 impl Deref for Timestamp {
@@ -135,7 +194,17 @@ impl Deref for Timestamp {
 
 impl Timestamp {
     pub fn new(t: Tm) -> Timestamp {
-        Timestamp { tm: t }
+        Timestamp {
+            tm: t,
+            utc_offset: None,
+        }
+    }
+
+    pub fn with_utc_offset(t: Tm) -> Timestamp {
+        Timestamp {
+            tm: t,
+            utc_offset: Some(t.tm_utcoff),
+        }
     }
 }
 
@@ -144,7 +213,7 @@ impl Serialize for Timestamp {
     where
         S: Serializer,
     {
-        strftime("%m-%d %H:%M:%S.%f", &self.tm)
+        time::strftime(TIMESTAMP_FORMAT, &self.tm)
             .map_err(|e| ::serde::ser::Error::custom(e.to_string()))?
             .serialize(serializer)
     }
@@ -163,7 +232,7 @@ impl<'de> Deserialize<'de> for Timestamp {
             where
                 E: ::serde::de::Error,
             {
-                strptime(str_data, "%m-%d %H:%M:%S.%f")
+                strptime(str_data, TIMESTAMP_FORMAT)
                     .map(Timestamp::new)
                     .map_err(|_| {
                         ::serde::de::Error::invalid_value(
@@ -191,24 +260,138 @@ pub struct Record {
     pub process: String,
     pub thread: String,
     pub raw: String,
+    /// Record is not matched by the filters but printed as context of a match.
+    /// Only set if context records are requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<bool>,
 }
 
 impl Record {
+    /// Separator between non adjacent groups of context records
+    pub fn separator() -> Record {
+        Record {
+            message: "--".to_owned(),
+            raw: "--".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_context(&self) -> bool {
+        self.context == Some(true)
+    }
+
+    pub fn is_separator(&self) -> bool {
+        self.timestamp.is_none() && self.tag.is_empty() && self.raw == "--"
+    }
+
+    /// Marker inserted by the user at the current host time. The raw line
+    /// is in the logcat format to be recognized when a file is read again.
+    pub fn marker(label: &str) -> Record {
+        let mut tm = now();
+        // Timestamps carry the full year like the parsed ones
+        tm.tm_year += 1900;
+        let time = strftime(LOGCAT_TIMESTAMP_FORMAT, &tm);
+        Record {
+            timestamp: Some(Timestamp::with_utc_offset(tm)),
+            message: label.to_owned(),
+            level: Level::Info,
+            tag: MARKER_TAG.to_owned(),
+            process: "0".to_owned(),
+            thread: "0".to_owned(),
+            raw: format!("{}     0     0 I {}: {}", time, MARKER_TAG, label),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_marker(&self) -> bool {
+        self.tag == MARKER_TAG && self.process == "0" && self.thread == "0"
+    }
+
     pub fn format(&self, format: &Format) -> Result<String, Error> {
         match *format {
-            Format::Csv => {
-                let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
-                wtr.serialize(self)?;
-                wtr.flush()?;
-                Ok(String::from_utf8(wtr.into_inner().unwrap())?
-                    .trim_right_matches('\n')
-                    .to_owned())
+            Format::Csv => to_csv(self),
+            Format::Html | Format::Human | Format::Template => {
+                Err(format_err!("The {} format requires a renderer", format))
             }
-            Format::Html => unimplemented!(),
-            Format::Human => unimplemented!(),
-            Format::Json => ::serde_json::to_string(self)
-                .map_err(|e| format_err!("Json serialization error: {}", e)),
+            Format::Json => to_json(self),
             Format::Raw => Ok(self.raw.clone()),
+            _ => {
+                let timestamp = self.timestamp
+                    .as_ref()
+                    .map(|t| strftime(LOGCAT_TIMESTAMP_FORMAT, t));
+                Ok(self.to_logcat(format, timestamp.as_ref().map(|t| t.as_str())))
+            }
+        }
+    }
+
+    /// Layout of `logcat -v <format>`. Records that were not parsed are
+    /// passed as they came in.
+    fn to_logcat(&self, format: &Format, timestamp: Option<&str>) -> String {
+        if self.tag.is_empty() && self.level == Level::None {
+            return self.raw.clone();
+        }
+        let time = timestamp.map(|t| format!("{} ", t)).unwrap_or_default();
+        let level = &self.level;
+        let tag = &self.tag;
+        let pid = &self.process;
+        let tid = &self.thread;
+        let message = &self.message;
+        match *format {
+            Format::Brief => format!("{}/{:<8}({:>5}): {}", level, tag, pid, message),
+            Format::Long => format!("[ {}{:>5}:{:>5} {}/{:<8} ]\n{}\n", time, pid, tid, level, tag, message),
+            Format::Process => format!("{}({:>5}) {}  ({})", level, pid, message, tag),
+            Format::Tag => format!("{}/{:<8}: {}", level, tag, message),
+            Format::Thread => format!("{}({:>5}:{:>5}) {}", level, pid, tid, message),
+            Format::Threadtime => format!("{}{:>5} {:>5} {} {:<8}: {}", time, pid, tid, level, tag, message),
+            Format::Time => format!("{}{}/{:<8}({:>5}): {}", time, level, tag, pid, message),
+            _ => unreachable!("{} is not a logcat format", format),
         }
     }
+
+    /// Format with `timestamp` in place of the formatted record timestamp
+    pub fn format_with_timestamp(&self, format: &Format, timestamp: &str) -> Result<String, Error> {
+        let stamped = Stamped {
+            timestamp,
+            message: &self.message,
+            level: &self.level,
+            tag: &self.tag,
+            process: &self.process,
+            thread: &self.thread,
+            raw: &self.raw,
+            context: self.context,
+        };
+        match *format {
+            Format::Csv => to_csv(&stamped),
+            Format::Json => to_json(&stamped),
+            Format::Html | Format::Human | Format::Raw | Format::Template => self.format(format),
+            _ => Ok(self.to_logcat(format, Some(timestamp))),
+        }
+    }
+}
+
+/// Record with a preformatted timestamp
+#[derive(Serialize)]
+struct Stamped<'a> {
+    timestamp: &'a str,
+    message: &'a str,
+    level: &'a Level,
+    tag: &'a str,
+    process: &'a str,
+    thread: &'a str,
+    raw: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<bool>,
+}
+
+fn to_csv<T: Serialize>(value: &T) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    wtr.serialize(value)?;
+    wtr.flush()?;
+    Ok(String::from_utf8(wtr.into_inner().unwrap())?
+        .trim_right_matches('\n')
+        .to_owned())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    ::serde_json::to_string(value).map_err(|e| format_err!("Json serialization error: {}", e))
 }
@@ -0,0 +1,252 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::Error;
+use filewriter::FileWriter;
+use outcome::Condition;
+use profiles::Profile;
+use record::Record;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{stderr, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Handle;
+use utils::parse_duration;
+
+/// Default number of records written after a trigger
+const DEFAULT_DUMP_AFTER: usize = 100;
+/// Default filename of dumps
+const DEFAULT_DUMP_FILE: &str = "rogcat-dump.log";
+
+/// Capacity of the ring buffer
+#[derive(Debug, PartialEq)]
+enum Limit {
+    Records(usize),
+    Duration(Duration),
+}
+
+impl FromStr for Limit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Limit, Error> {
+        usize::from_str(s)
+            .ok()
+            .map(Limit::Records)
+            .or_else(|| {
+                parse_duration(s)
+                    .and_then(|d| d.to_std().ok())
+                    .map(Limit::Duration)
+            })
+            .ok_or_else(|| format_err!("Invalid flight recorder size: {}", s))
+    }
+}
+
+struct State {
+    after: usize,
+    buffer: VecDeque<(Instant, Record)>,
+    conditions: Vec<Condition>,
+    limit: Limit,
+    /// Records still to write after the last trigger
+    remaining: usize,
+    writer: Option<FileWriter>,
+}
+
+impl State {
+    fn push(&mut self, record: &Record, now: Instant) {
+        self.buffer.push_back((now, record.clone()));
+        match self.limit {
+            Limit::Records(n) => while self.buffer.len() > n {
+                self.buffer.pop_front();
+            },
+            Limit::Duration(d) => while self.buffer
+                .front()
+                .map(|&(t, _)| now.duration_since(t) > d)
+                .unwrap_or(false)
+            {
+                self.buffer.pop_front();
+            },
+        }
+    }
+
+    /// Write the buffer and continue with the next `after` records
+    fn trigger(&mut self) -> Result<(), Error> {
+        if self.remaining == 0 {
+            if let Some(ref mut writer) = self.writer {
+                for &(_, ref r) in &self.buffer {
+                    writer.write(r)?;
+                }
+            }
+        }
+        self.remaining = self.after;
+        self.finish_if_done()
+    }
+
+    fn finish_if_done(&mut self) -> Result<(), Error> {
+        if self.remaining == 0 {
+            if let Some(ref mut writer) = self.writer {
+                if let Some(file) = writer.current_file() {
+                    writeln!(stderr(), "Flight recorder dumped to {}", file.display()).ok();
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, record: &Option<Record>, now: Instant) -> Result<(), Error> {
+        match *record {
+            Some(ref record) => {
+                self.push(record, now);
+                if self.remaining > 0 {
+                    if let Some(ref mut writer) = self.writer {
+                        writer.write(record)?;
+                    }
+                    self.remaining -= 1;
+                    if self.conditions.iter().any(|c| c.is_match(record)) {
+                        self.remaining = self.after;
+                    }
+                    self.finish_if_done()
+                } else if self.conditions.iter().any(|c| c.is_match(record)) {
+                    self.trigger()
+                } else {
+                    Ok(())
+                }
+            }
+            None if self.remaining > 0 => {
+                self.remaining = 0;
+                self.finish_if_done()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keeps the most recent records in memory and writes them to a new
+/// file when a --dump-on condition matches or SIGUSR1 is received
+pub struct Recorder {
+    state: Option<Rc<RefCell<State>>>,
+}
+
+impl<'a> Recorder {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile, handle: &Handle) -> Result<Self, Error> {
+        let limit = match args.value_of("flight_recorder") {
+            Some(l) => Limit::from_str(l)?,
+            None => return Ok(Recorder { state: None }),
+        };
+        let after = if args.is_present("dump_after") {
+            value_t!(args, "dump_after", usize)?
+        } else {
+            DEFAULT_DUMP_AFTER
+        };
+        let conditions = args.values_of("dump_on")
+            .map(|v| v.map(Condition::new).collect::<Result<Vec<Condition>, Error>>())
+            .unwrap_or_else(|| Ok(vec![]))?;
+        let filename = PathBuf::from(args.value_of("dump_file").unwrap_or(DEFAULT_DUMP_FILE));
+
+        let state = Rc::new(RefCell::new(State {
+            after,
+            buffer: VecDeque::new(),
+            conditions,
+            limit,
+            remaining: 0,
+            writer: Some(FileWriter::dump(args, profile, filename)?),
+        }));
+        Self::signal(&state, handle);
+
+        Ok(Recorder { state: Some(state) })
+    }
+
+    #[cfg(unix)]
+    fn signal(state: &Rc<RefCell<State>>, handle: &Handle) {
+        use futures::{Future, Stream};
+        use tokio_signal::unix::{Signal, SIGUSR1};
+
+        let state = state.clone();
+        let usr1 = Signal::new(SIGUSR1, handle)
+            .flatten_stream()
+            .map_err(|e| e.into())
+            .for_each(move |_| state.borrow_mut().trigger())
+            .map_err(|e: Error| {
+                writeln!(stderr(), "Flight recorder failed: {}", e).ok();
+            });
+        handle.spawn(usr1);
+    }
+
+    #[cfg(not(unix))]
+    fn signal(_: &Rc<RefCell<State>>, _: &Handle) {}
+
+    pub fn process(&self, record: &Option<Record>) -> Result<(), Error> {
+        match self.state {
+            Some(ref state) => state.borrow_mut().process(record, Instant::now()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+fn state(limit: Limit, after: usize) -> State {
+    State {
+        after,
+        buffer: VecDeque::new(),
+        conditions: vec![Condition::new("^FATAL").unwrap()],
+        limit,
+        remaining: 0,
+        writer: None,
+    }
+}
+
+#[cfg(test)]
+fn record(message: &str) -> Option<Record> {
+    Some(Record {
+        message: message.to_owned(),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn limit() {
+    assert_eq!(Limit::from_str("100").unwrap(), Limit::Records(100));
+    assert_eq!(
+        Limit::from_str("5m").unwrap(),
+        Limit::Duration(Duration::from_secs(300))
+    );
+    assert!(Limit::from_str("5x").is_err());
+
+    let now = Instant::now();
+    let mut s = state(Limit::Records(2), 0);
+    for m in &["A", "B", "C"] {
+        s.process(&record(m), now).unwrap();
+    }
+    let messages = s.buffer.iter().map(|r| r.1.message.as_str()).collect::<Vec<&str>>();
+    assert_eq!(messages, vec!["B", "C"]);
+
+    let mut s = state(Limit::Duration(Duration::from_secs(60)), 0);
+    s.process(&record("A"), now).unwrap();
+    s.process(&record("B"), now + Duration::from_secs(30)).unwrap();
+    s.process(&record("C"), now + Duration::from_secs(61)).unwrap();
+    let messages = s.buffer.iter().map(|r| r.1.message.as_str()).collect::<Vec<&str>>();
+    assert_eq!(messages, vec!["B", "C"]);
+}
+
+#[test]
+fn trigger() {
+    let now = Instant::now();
+    let mut s = state(Limit::Records(10), 2);
+    s.process(&record("A"), now).unwrap();
+    s.process(&record("FATAL"), now).unwrap();
+    assert_eq!(s.remaining, 2);
+    s.process(&record("B"), now).unwrap();
+    // Another trigger extends the dump
+    s.process(&record("FATAL"), now).unwrap();
+    assert_eq!(s.remaining, 2);
+    s.process(&record("C"), now).unwrap();
+    s.process(&None, now).unwrap();
+    assert_eq!(s.remaining, 0);
+}
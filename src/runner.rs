@@ -15,6 +15,7 @@ use record::{Format, Level};
 use std::io::BufReader;
 use std::process::{Command, Stdio};
 use tokio_core::reactor::Handle;
+use timewindow::TimeWindow;
 use tokio_process::{Child, CommandExt};
 use utils::lossy_lines;
 
@@ -84,6 +85,12 @@ pub fn runner<'a>(args: &ArgMatches<'a>, handle: Handle) -> Result<RStream, Erro
             restart = false;
         };
 
+        // A restart would print the records since the bound again
+        if let Some(ref since) = *TimeWindow::new(args)?.since() {
+            logcat_args.push(format!("-T {}", since.epoch()));
+            restart = false;
+        }
+
         if args.is_present("dump") {
             logcat_args.push("-d".to_owned());
             restart = false;
@@ -0,0 +1,215 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use config_get;
+use failure::{err_msg, Error};
+use handlebars::{no_escape, Handlebars, Helper, JsonRender, RenderContext, RenderError};
+use layout::{Align, Column, Field, Truncate};
+use profiles::Profile;
+use record::{Level, Record, TIMESTAMP_FORMAT};
+use serde_json::to_string;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+use theme::{Color, Theme};
+use time::{self, strptime};
+use timestamps::strftime;
+
+/// Fields of a record passed to templates
+#[derive(Serialize)]
+struct Data<'a> {
+    timestamp: &'a str,
+    level: String,
+    tag: &'a str,
+    process: &'a str,
+    thread: &'a str,
+    message: &'a str,
+    raw: &'a str,
+    context: bool,
+}
+
+/// User defined output format rendered with handlebars for each record
+pub struct Template {
+    handlebars: Handlebars,
+}
+
+impl<'a> Template {
+    /// Template of the command line, the profile or the config. Values
+    /// starting with @ name a file that contains the template. Colors are
+    /// rendered with `theme` if set.
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile, theme: Option<Theme>) -> Result<Template, Error> {
+        let template = args.value_of("template")
+            .map(|t| t.to_owned())
+            .or_else(|| profile.template().clone())
+            .or_else(|| config_get("template"))
+            .ok_or_else(|| err_msg("The template format requires a --template"))?;
+        let template = if template.starts_with('@') {
+            let mut content = String::new();
+            File::open(&template[1..])
+                .and_then(|mut f| f.read_to_string(&mut content))
+                .map_err(|e| format_err!("Failed to read template {}: {}", &template[1..], e))?;
+            // Files usually end with a newline that is not part of the template
+            if content.ends_with('\n') {
+                content.pop();
+            }
+            content
+        } else {
+            template
+        };
+        Template::with_str(&template, theme)
+    }
+
+    fn with_str(template: &str, theme: Option<Theme>) -> Result<Template, Error> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(no_escape);
+        handlebars
+            .register_template_string("record", template)
+            .map_err(|e| format_err!("Invalid template: {}", e))?;
+        handlebars.register_helper(
+            "color",
+            Box::new(move |h: &Helper, _: &Handlebars, rc: &mut RenderContext| {
+                let color = param(h, 0)?;
+                let value = param(h, 1)?;
+                let color = match color.as_str() {
+                    "level" => {
+                        let level = rc.context().data().get("level").map(|l| l.render());
+                        theme.as_ref().map(|t| t.level(&Level::from(level.unwrap_or_default().as_str())))
+                    }
+                    c => match Color::from_str(c) {
+                        Ok(c) => theme.as_ref().map(|_| c),
+                        Err(e) => return Err(RenderError::new(e.to_string())),
+                    },
+                };
+                let rendered = match color {
+                    Some(color) => format!("{}{}\x1b[0m", color.ansi(), value),
+                    None => value,
+                };
+                rc.writer.write_all(rendered.as_bytes())?;
+                Ok(())
+            }),
+        );
+        handlebars.register_helper("json", Box::new(json_helper));
+        handlebars.register_helper("pad", Box::new(pad_helper));
+        handlebars.register_helper("time", Box::new(time_helper));
+        Ok(Template { handlebars })
+    }
+
+    /// Render `record`. `timestamp` replaces the record timestamp if set.
+    pub fn render(&self, record: &Record, timestamp: Option<&str>) -> Result<String, Error> {
+        let formatted = match timestamp {
+            Some(_) => None,
+            None => record
+                .timestamp
+                .as_ref()
+                .map(|t| time::strftime(TIMESTAMP_FORMAT, t).unwrap_or_default()),
+        };
+        let data = Data {
+            timestamp: timestamp.or_else(|| formatted.as_ref().map(|t| t.as_str())).unwrap_or(""),
+            level: record.level.to_string(),
+            tag: &record.tag,
+            process: &record.process,
+            thread: &record.thread,
+            message: &record.message,
+            raw: &record.raw,
+            context: record.is_context(),
+        };
+        self.handlebars
+            .render("record", &data)
+            .map_err(|e| format_err!("Failed to render template: {}", e))
+    }
+}
+
+/// Rendered value of parameter `n`
+fn param(h: &Helper, n: usize) -> Result<String, RenderError> {
+    h.param(n)
+        .map(|p| p.value().render())
+        .ok_or_else(|| RenderError::new(format!("Missing parameter {} of helper {}", n, h.name())))
+}
+
+/// `{{json value}}` writes `value` as JSON
+fn json_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let value = h.param(0)
+        .ok_or_else(|| RenderError::new("Missing parameter of helper json"))?;
+    let json = to_string(value.value()).map_err(|e| RenderError::new(e.to_string()))?;
+    rc.writer.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// `{{pad value width [align="left|right|center"] [truncate="clip|clip-start|ellipsis|vowels"]}}`
+/// pads or shortens `value` to `width` columns
+fn pad_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let value = param(h, 0)?;
+    let width = param(h, 1)?;
+    let width = usize::from_str(&width).map_err(|_| RenderError::new(format!("Invalid width: {}", width)))?;
+    let align = match h.hash_get("align").map(|a| a.value().render()) {
+        Some(ref a) if a == "right" => Align::Right,
+        Some(ref a) if a == "center" => Align::Center,
+        Some(ref a) if a != "left" => return Err(RenderError::new(format!("Invalid alignment: {}", a))),
+        _ => Align::Left,
+    };
+    let truncate = match h.hash_get("truncate").map(|t| t.value().render()) {
+        Some(t) => Truncate::from_str(&t).map_err(|e| RenderError::new(e.to_string()))?,
+        None => Truncate::Clip,
+    };
+    let column = Column {
+        field: Field::Message,
+        align: None,
+        width: None,
+        truncate: None,
+    };
+    rc.writer
+        .write_all(column.format(&value, width, align, truncate).as_bytes())?;
+    Ok(())
+}
+
+/// `{{time timestamp format}}` formats `timestamp` with the strftime `format`.
+/// `%3f` are the milliseconds. Timestamps that are not a point in time
+/// e.g. of `--time delta` are written unchanged.
+fn time_helper(h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let timestamp = param(h, 0)?;
+    let format = param(h, 1)?;
+    let rendered = match strptime(&timestamp, TIMESTAMP_FORMAT) {
+        Ok(tm) => strftime(&format, &tm),
+        Err(_) => timestamp,
+    };
+    rc.writer.write_all(rendered.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn record() -> Record {
+    Record {
+        timestamp: Some(::record::Timestamp::new(strptime("07-01 14:13:14.446", "%m-%d %H:%M:%S.%f").unwrap())),
+        message: "\"batt\" 29000 mC".to_owned(),
+        level: Level::Info,
+        tag: "ThermalEngine".to_owned(),
+        process: "225".to_owned(),
+        thread: "295".to_owned(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn template_helpers() {
+    let render = |t: &str| Template::with_str(t, None).unwrap().render(&record(), None).unwrap();
+    assert_eq!(render("{{level}} {{tag}}: {{message}}"), "I ThermalEngine: \"batt\" 29000 mC");
+    assert_eq!(render("{{json message}}"), r#""\"batt\" 29000 mC""#);
+    assert_eq!(render("[{{pad process 5 align=\"right\"}}]"), "[  225]");
+    assert_eq!(render("[{{pad tag 8 truncate=\"ellipsis\"}}]"), "[Thermal…]");
+    assert_eq!(render("{{time timestamp \"%H:%M:%S.%3f\"}}"), "14:13:14.446");
+    assert_eq!(render("{{color \"red\" tag}}"), "ThermalEngine");
+    let template = Template::with_str("{{color \"level\" level}}", Some(Theme::dark())).unwrap();
+    assert_eq!(template.render(&record(), None).unwrap(), "\x1b[32mI\x1b[0m");
+    assert_eq!(
+        Template::with_str("{{time timestamp \"%S\"}}", None)
+            .unwrap()
+            .render(&record(), Some("1.250"))
+            .unwrap(),
+        "1.250"
+    );
+    assert!(Template::with_str("{{#if}", None).is_err());
+}
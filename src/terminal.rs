@@ -9,68 +9,185 @@ use clap::ArgMatches;
 use config_get;
 use failure::{err_msg, Error};
 use futures::{Async, AsyncSink, Poll, Sink, StartSend};
+use highlight::{runs, Highlights};
+use layout::{Align, Field, Item, Layout, Truncate};
+use pretty::{Pretty, Syntax};
 use profiles::*;
-use record::{Format, Level, Record};
+use record::{Format, Record};
 use regex::Regex;
 use std::cmp::max;
 use std::collections::HashMap;
-use std::io::Write;
+use std::env::var;
+use std::io::{Stdout, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
 use std::str::FromStr;
-use term::color::*;
-use term::{stdout, Attr, StdoutTerminal};
+use term::terminfo::{TermInfo, TerminfoTerminal};
+use term::stdout;
+use template::Template;
+use theme::{Color, Theme};
 use time::Tm;
-use utils::terminal_width;
+use timestamps::{Mode, Timestamps};
+use unicode_width::UnicodeWidthStr;
+use utils::{sanitize, terminal_width, wrap};
 
+type Term<W> = Box<::term::Terminal<Output = W> + Send>;
+
+/// Pager if PAGER is unset and the shell that runs it
+#[cfg(not(target_os = "windows"))]
+const PAGER: &str = "less";
 #[cfg(not(target_os = "windows"))]
-pub const DIMM_COLOR: u16 = 243;
+const SHELL: [&str; 2] = ["sh", "-c"];
 #[cfg(target_os = "windows")]
-pub const DIMM_COLOR: u16 = WHITE;
-
+const PAGER: &str = "more";
 #[cfg(target_os = "windows")]
-fn hashed_color(i: &str) -> u16 {
-    i.bytes().fold(42u16, |c, x| (c ^ u16::from(x))) % 15 + 1
+const SHELL: [&str; 2] = ["cmd", "/C"];
+
+/// Set the foreground or background color. Truecolors are written as
+/// escape sequences since terminfo doesn't know about them.
+fn set_color<W: Write>(term: &mut Term<W>, color: Color, background: bool) -> Result<(), Error> {
+    match color {
+        Color::Indexed(c) if background => term.bg(c).map_err(|e| e.into()),
+        Color::Indexed(c) => term.fg(c).map_err(|e| e.into()),
+        Color::Rgb(r, g, b) => {
+            let code = if background { 48 } else { 38 };
+            write!(term, "\x1b[{};2;{};{};{}m", code, r, g, b).map_err(|e| e.into())
+        }
+    }
 }
 
-#[cfg(not(target_os = "windows"))]
-fn hashed_color(i: &str) -> u16 {
-    // Some colors are hard to read on (at least) dark terminals
-    // and others seem just ugly to me...
-    match i.bytes().fold(42u16, |c, x| (c ^ u16::from(x))) {
-        c @ 0...1 => c + 2,
-        c @ 16...21 => c + 6,
-        c @ 52...55 | c @ 126...129 => c + 4,
-        c @ 163...165 | c @ 200...201 => c + 3,
-        c @ 207 => c + 1,
-        c @ 232...240 => c + 9,
-        c => c,
+/// Terminfo of a 256 color ANSI terminal. Colored files do not depend
+/// on the terminal rogcat runs in.
+fn ansi_terminfo() -> TermInfo {
+    let mut numbers = HashMap::new();
+    numbers.insert("colors", 256u16);
+    let mut strings = HashMap::new();
+    strings.insert("sgr0", b"\x1b[0m".to_vec());
+    strings.insert(
+        "setaf",
+        b"\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m".to_vec(),
+    );
+    strings.insert(
+        "setab",
+        b"\x1b[%?%p1%{8}%<%t4%p1%d%e%p1%{16}%<%t10%p1%{8}%-%d%e48;5;%p1%d%;m".to_vec(),
+    );
+    TermInfo {
+        names: vec!["ansi-256color".to_owned()],
+        bools: HashMap::new(),
+        numbers,
+        strings,
     }
 }
 
-pub struct Terminal {
+pub struct Terminal<W: Write = Stdout> {
     beginning_of: Regex,
+    buffer: String,
+    buffer_switch: Regex,
+    buffer_width: usize,
     color: bool,
     date_format: (String, usize),
+    device: String,
     diff_width: usize,
     format: Format,
-    highlight: Vec<Regex>,
-    no_dimm: bool,
+    highlights: Highlights,
+    layout: Layout,
+    pretty: Pretty,
     process_width: usize,
     shorten_tag: bool,
     tag_timestamps: HashMap<String, Tm>,
     tag_width: Option<usize>,
-    term: Box<StdoutTerminal>,
+    template: Option<Template>,
+    term: Term<W>,
+    theme: Theme,
     thread_width: usize,
     time_diff: bool,
-    vovels: Regex,
+    timestamp_width: usize,
+    timestamps: Timestamps,
+    tty: bool,
+    width: Option<usize>,
 }
 
 impl<'a> Terminal {
     pub fn new(args: &ArgMatches<'a>, profile: &Profile) -> Result<Self, Error> {
-        let mut hl = profile.highlight().clone();
-        if args.is_present("highlight") {
-            hl.extend(values_t!(args.values_of("highlight"), String)?);
+        let term = stdout().ok_or_else(|| err_msg("Failed to lock terminal"))?;
+        Terminal::with_term(args, profile, term, ::atty::is(Stream::Stdout))
+    }
+}
+
+impl<'a> Terminal<ChildStdin> {
+    /// Terminal that writes to a pager. Returns the terminal and the pager
+    /// process that must be waited for once the terminal is dropped.
+    pub fn pager(args: &ArgMatches<'a>, profile: &Profile) -> Result<(Self, Child), Error> {
+        let pager = var("PAGER").unwrap_or_else(|_| PAGER.to_owned());
+        let mut command = Command::new(SHELL[0]);
+        command.arg(SHELL[1]).arg(&pager).stdin(Stdio::piped());
+        // Let less and lv pass colors like git does
+        if var("LESS").is_err() {
+            command.env("LESS", "FRX");
+        }
+        if var("LV").is_err() {
+            command.env("LV", "-c");
         }
-        let highlight = hl.iter().flat_map(|h| Regex::new(h)).collect();
+        let mut child = command
+            .spawn()
+            .map_err(|e| format_err!("Failed to spawn pager {}: {}", pager, e))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| err_msg("Failed to open pager input"))?;
+        let term = TerminfoTerminal::new(stdin).ok_or_else(|| err_msg("Failed to read terminfo"))?;
+        let terminal = Terminal::with_term(args, profile, Box::new(term), true)?;
+        Ok((terminal, child))
+    }
+}
+
+impl<'a> Terminal<Vec<u8>> {
+    /// Terminal that renders into a buffer for files. Lines are colored
+    /// with `--color always` only and not wrapped unless a width is set.
+    /// Messages are never pretty printed.
+    pub fn file(args: &ArgMatches<'a>, profile: &Profile) -> Result<Self, Error> {
+        let terminfo = if args.value_of("color") == Some("always") {
+            ansi_terminfo()
+        } else {
+            TermInfo {
+                names: vec![],
+                bools: HashMap::new(),
+                numbers: HashMap::new(),
+                strings: HashMap::new(),
+            }
+        };
+        let term = TerminfoTerminal::new_with_terminfo(vec![], terminfo);
+        let mut terminal = Terminal::with_term(args, profile, Box::new(term), false)?;
+        terminal.pretty = Pretty::default();
+        terminal.width = terminal.width.or(Some(0));
+        Ok(terminal)
+    }
+
+    /// Terminal that renders into a buffer instead of stdout
+    #[cfg(not(target_os = "windows"))]
+    pub fn buffered(args: &ArgMatches<'a>, profile: &Profile) -> Result<Self, Error> {
+        let term = TerminfoTerminal::new(vec![])
+            .ok_or_else(|| err_msg("Failed to read terminfo"))?;
+        Terminal::with_term(args, profile, Box::new(term), true)
+    }
+
+    /// Render `record` into lines
+    pub fn render(&mut self, record: &Record) -> Result<Vec<String>, Error> {
+        self.print_record(record)?;
+        let output = self.term.get_mut().split_off(0);
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .map(|l| l.to_owned())
+            .collect())
+    }
+}
+
+impl<'a, W: Write> Terminal<W> {
+    fn with_term(
+        args: &ArgMatches<'a>,
+        profile: &Profile,
+        term: Term<W>,
+        tty: bool,
+    ) -> Result<Self, Error> {
 
         let format = args.value_of("format")
             .and_then(|f| Format::from_str(f).ok())
@@ -79,19 +196,29 @@ impl<'a> Terminal {
             return Err(err_msg("HTML format is unsupported when writing to files"));
         }
 
-        let term = stdout().ok_or_else(|| err_msg("Failed to lock terminal"))?;
         let color = {
             match args.value_of("color")
                 .unwrap_or_else(|| config_get("terminal_color").unwrap_or_else(|| "auto"))
             {
                 "always" => true,
                 "never" => false,
-                "auto" | _ => ::atty::is(Stream::Stdout) && term.supports_color(),
+                "auto" | _ => tty && term.supports_color(),
             }
         };
         let hide_timestamp = args.is_present("hide_timestamp")
             || config_get("terminal_hide_timestamp").unwrap_or(false);
         let no_dimm = args.is_present("no_dimm") || config_get("terminal_no_dimm").unwrap_or(false);
+        let theme = Theme::new(no_dimm)?;
+        let pretty = args.values_of("pretty")
+            .map(|v| v.map(|p| p.to_owned()).collect::<Vec<String>>())
+            .or_else(|| config_get("terminal_pretty"))
+            .map(|p| Pretty {
+                // A plain --pretty selects json
+                json: p.is_empty() || p.iter().any(|p| p == "json"),
+                kv: p.iter().any(|p| p == "kv"),
+            })
+            .unwrap_or_default();
+        let highlights = Highlights::new(args, profile, theme.highlight)?;
         let shorten_tag =
             args.is_present("shorten_tags") || config_get("terminal_shorten_tags").unwrap_or(false);
         let show_date =
@@ -100,58 +227,159 @@ impl<'a> Terminal {
         let time_diff = args.is_present("show_time_diff")
             || config_get("terminal_show_time_diff").unwrap_or(false);
         let time_diff_width = config_get("terminal_time_diff_width").unwrap_or(8);
+        let layout = match profile.layout().clone().or_else(|| config_get("terminal_layout")) {
+            Some(l) => Layout::from_str(&l)?,
+            None => Layout::default(),
+        };
+        let date_format = if show_date {
+            if hide_timestamp {
+                ("%m-%d".to_owned(), 5)
+            } else {
+                ("%m-%d %H:%M:%S.%f".to_owned(), 18)
+            }
+        } else if hide_timestamp {
+            ("".to_owned(), 0)
+        } else {
+            ("%H:%M:%S.%f".to_owned(), 12)
+        };
+        let timestamp_width = date_format.1;
+        let width = match args.value_of("width") {
+            Some(w) => Some(usize::from_str(w).map_err(|_| format_err!("Invalid width: {}", w))?),
+            None => config_get("terminal_width"),
+        };
+        let template = if format == Format::Template {
+            Some(Template::new(args, profile, if color { Some(theme.clone()) } else { None })?)
+        } else {
+            None
+        };
 
         Ok(Terminal {
             beginning_of: Regex::new(r"--------- beginning of.*").unwrap(),
+            buffer: String::new(),
+            buffer_switch: Regex::new(r"^--------- (?:beginning of|switch to) (\S+)").unwrap(),
+            buffer_width: 0,
             color,
-            date_format: if show_date {
-                if hide_timestamp {
-                    ("%m-%d".to_owned(), 5)
-                } else {
-                    ("%m-%d %H:%M:%S.%f".to_owned(), 18)
-                }
-            } else if hide_timestamp {
-                ("".to_owned(), 0)
-            } else {
-                ("%H:%M:%S.%f".to_owned(), 12)
-            },
+            date_format,
+            // adb selects the device by ANDROID_SERIAL if set
+            device: var("ANDROID_SERIAL").unwrap_or_default(),
             diff_width: if time_diff { time_diff_width } else { 0 },
             format,
-            highlight,
-            no_dimm,
+            highlights,
+            layout,
+            pretty,
             process_width: 0,
             shorten_tag,
             tag_timestamps: HashMap::new(),
             tag_width,
+            template,
             term,
+            theme,
             thread_width: 0,
             time_diff,
-            vovels: Regex::new(r"a|e|i|o|u").unwrap(),
+            timestamp_width,
+            timestamps: Timestamps::new(args)?,
+            tty,
+            width,
         })
     }
 
-    /// Filter some unreadable (on dark background) or nasty colors
+    /// Columns to wrap at. A width of 0 disables wrapping.
+    fn width(&self) -> Option<usize> {
+        match self.width {
+            Some(0) => None,
+            Some(width) => Some(width),
+            None => terminal_width(),
+        }
+    }
+
     fn print_record(&mut self, record: &Record) -> Result<(), Error> {
         match self.format {
-            Format::Csv | Format::Json | Format::Raw => {
-                println!("{}", record.format(&self.format)?);
-                Ok(())
-            }
             Format::Human => self.print_human(record),
             Format::Html => {
                 unreachable!("Unimplemented format html");
             }
+            _ => {
+                let timestamp = match record.timestamp {
+                    Some(ref ts) if self.format != Format::Raw && self.timestamps.mode() != Mode::Absolute => {
+                        Some(self.timestamps.format(ts, self.format.timestamp_format()))
+                    }
+                    _ => None,
+                };
+                let line = match (&self.template, timestamp) {
+                    (&Some(ref template), timestamp) => template.render(record, timestamp.as_ref().map(|t| t.as_str()))?,
+                    (&None, Some(timestamp)) => record.format_with_timestamp(&self.format, &timestamp)?,
+                    (&None, None) => record.format(&self.format)?,
+                };
+                if self.tty {
+                    // The long format and templates span multiple lines
+                    for line in line.split('\n') {
+                        writeln!(self.term, "{}", sanitize(line))?;
+                    }
+                } else {
+                    writeln!(self.term, "{}", line)?;
+                }
+                Ok(())
+            }
         }
     }
 
-    // TODO
-    // Rework this to use a more column based approach!
+    /// Markers are printed as a horizontal line with the time and label
+    fn print_marker(&mut self, record: &Record) -> Result<(), Error> {
+        let mut text = "───".to_owned();
+        if let Some(ref ts) = record.timestamp {
+            let time = ::time::strftime("%H:%M:%S", ts).unwrap_or_default();
+            text.push_str(&format!(" {}.{:03}", time, ts.tm_nsec / 1_000_000));
+        }
+        if !record.message.is_empty() {
+            text.push_str(&format!(" {}", record.message));
+        }
+        text.push(' ');
+        let fill = self.width().map(|w| w.saturating_sub(text.width())).unwrap_or(3);
+        text.push_str(&"─".repeat(max(fill, 3)));
+
+        if self.color {
+            set_color(&mut self.term, self.theme.highlight, false)?;
+            write!(self.term, "{}", text)?;
+            self.term.reset()?;
+            writeln!(self.term)?;
+        } else {
+            writeln!(self.term, "{}", text)?;
+        }
+        Ok(())
+    }
+
     fn print_human(&mut self, record: &Record) -> Result<(), Error> {
-        let (timestamp, mut diff) = if let Some(ts) = record.timestamp.clone() {
-            let ts = *ts;
-            let timestamp = match ::time::strftime(&self.date_format.0, &ts) {
-                Ok(t) => t.chars().take(self.date_format.1).collect::<String>(),
-                Err(_) => (0..self.date_format.1).map(|_| " ").collect::<String>(),
+        // Device logs may contain escape sequences that mess up the terminal
+        let record = &Record {
+            message: sanitize(&record.message),
+            process: sanitize(&record.process),
+            tag: sanitize(&record.tag),
+            thread: sanitize(&record.thread),
+            ..record.clone()
+        };
+
+        if record.is_separator() {
+            if self.color {
+                set_color(&mut self.term, self.theme.dimm, false)?;
+                write!(self.term, "{}", record.message)?;
+                self.term.reset()?;
+                writeln!(self.term)?;
+            } else {
+                writeln!(self.term, "{}", record.message)?;
+            }
+            return Ok(());
+        }
+
+        if record.is_marker() {
+            return self.print_marker(record);
+        }
+
+        let (timestamp, mut diff) = if let Some(ref timestamp) = record.timestamp {
+            let ts = **timestamp;
+            let timestamp = if self.date_format.0.is_empty() {
+                "".to_owned()
+            } else {
+                self.timestamps.format(timestamp, &self.date_format.0)
             };
 
             let diff = if self.time_diff {
@@ -175,7 +403,7 @@ impl<'a> Terminal {
             ("".to_owned(), "".to_owned())
         };
 
-        let terminal_width = terminal_width();
+        let terminal_width = self.width();
         let tag_width = self.tag_width.unwrap_or_else(|| match terminal_width {
             Some(n) if n <= 80 => 15,
             Some(n) if n <= 90 => 20,
@@ -184,168 +412,234 @@ impl<'a> Terminal {
             _ => 35,
         });
 
-        let tag = {
-            let mut t = if self.beginning_of.is_match(&record.message) {
-                diff = "".to_owned();
-                self.tag_timestamps.clear();
-                // Print horizontal line if temrinal width is detectable
-                if let Some(width) = terminal_width {
-                    println!("{}", (0..width).map(|_| "─").collect::<String>());
-                }
-                // "beginnig of" messages never have a tag
-                record.message.clone()
-            } else {
-                record.tag.clone()
-            };
-
-            if t.chars().count() > tag_width {
-                if self.shorten_tag {
-                    t = self.vovels.replace_all(&t.to_owned(), "").to_string();
-                }
-                if t.chars().count() > tag_width {
-                    t.truncate(tag_width);
-                }
+        let tag = if self.beginning_of.is_match(&record.message) {
+            diff = "".to_owned();
+            self.tag_timestamps.clear();
+            // Print horizontal line if temrinal width is detectable
+            if let Some(width) = terminal_width {
+                writeln!(self.term, "{}", (0..width).map(|_| "─").collect::<String>())?;
             }
-            format!("{:>width$}", t, width = tag_width)
-        };
-
-        self.process_width = max(self.process_width, record.process.chars().count());
-        let pid = if record.process.is_empty() {
-            " ".repeat(self.process_width)
+            // "beginnig of" messages never have a tag
+            record.message.clone()
         } else {
-            format!("{:<width$}", record.process, width = self.process_width)
+            record.tag.clone()
         };
-        let tid = if record.thread.is_empty() {
-            if self.thread_width > 0 {
-                " ".repeat(self.thread_width + 1)
-            } else {
-                "".to_owned()
+        if let Some(buffer) = self.buffer_switch.captures(&record.message) {
+            self.buffer = buffer[1].to_owned();
+        }
+
+        self.buffer_width = max(self.buffer_width, self.buffer.width());
+        // Clock times are clipped to milliseconds, durations grow
+        match self.timestamps.mode() {
+            Mode::Delta | Mode::Epoch | Mode::Relative => {
+                self.timestamp_width = max(self.timestamp_width, timestamp.width())
             }
+            Mode::Absolute | Mode::Local | Mode::Utc => (),
+        }
+        self.process_width = max(self.process_width, record.process.width());
+        self.thread_width = max(self.thread_width, record.thread.width());
+
+        // Context records are dimmed or marked with dashes like grep does
+        let context = record.is_context();
+        let level = if context {
+            format!("-{}-", record.level)
         } else {
-            self.thread_width = max(self.thread_width, record.thread.chars().count());
-            format!(" {:>width$}", record.thread, width = self.thread_width)
+            format!(" {} ", record.level)
         };
+        let highlight = self.highlights.is_match(record);
+
+        // Render everything in front of the message into colored pieces.
+        // Columns of zero width swallow a adjacent space.
+        let theme = &self.theme;
+        let level_color = theme.level(&record.level);
+        let mut preamble: Vec<(String, Color, Option<Color>)> = vec![];
+        let mut collapse = false;
+        for item in self.layout.preamble() {
+            match *item {
+                Item::Text(ref text) => {
+                    let text = if collapse && text.starts_with(' ') {
+                        &text[1..]
+                    } else {
+                        text
+                    };
+                    collapse = false;
+                    preamble.push((text.to_owned(), theme.dimm, None));
+                }
+                Item::Column(ref column) => {
+                    let hashed = |c: Color| if context { theme.dimm } else { c };
+                    let (value, width, align, color, background) = match column.field {
+                        Field::Buffer => (&self.buffer, self.buffer_width, Align::Left, theme.dimm, None),
+                        Field::Device => (&self.device, self.device.chars().count(), Align::Left, theme.dimm, None),
+                        Field::Diff => (&diff, self.diff_width, Align::Right, theme.dimm, None),
+                        Field::Level if context => (&level, 3, Align::Left, level_color, None),
+                        Field::Level => (&level, 3, Align::Left, theme.badge, Some(level_color)),
+                        Field::Pid => (
+                            &record.process,
+                            self.process_width,
+                            Align::Left,
+                            hashed(theme.process(&record.process, &record.process)),
+                            None,
+                        ),
+                        Field::Tag => (&tag, tag_width, Align::Right, hashed(theme.tag(&record.tag, &tag)), None),
+                        Field::Tid => (
+                            &record.thread,
+                            self.thread_width,
+                            Align::Right,
+                            hashed(theme.process(&record.process, &record.thread)),
+                            None,
+                        ),
+                        Field::Timestamp => (
+                            &timestamp,
+                            self.timestamp_width,
+                            Align::Left,
+                            highlight.unwrap_or(theme.dimm),
+                            None,
+                        ),
+                        Field::Message => unreachable!("Message is not part of the preamble"),
+                    };
+                    let truncate = if column.field == Field::Tag && self.shorten_tag {
+                        Truncate::Vowels
+                    } else {
+                        Truncate::Clip
+                    };
+                    let text = column.format(value, width, align, truncate);
+                    if text.is_empty() {
+                        match preamble.last_mut() {
+                            Some(&mut (ref mut t, _, None)) if t.ends_with(' ') => {
+                                t.pop();
+                            }
+                            _ => collapse = true,
+                        }
+                    } else if column.field == Field::Tag {
+                        let highlights = self.highlights.tag(&text);
+                        for (run, highlight) in runs(&text, &highlights) {
+                            match highlight {
+                                Some(h) => preamble.push((run.to_owned(), theme.badge, Some(h))),
+                                None => preamble.push((run.to_owned(), color, background)),
+                            }
+                        }
+                    } else {
+                        preamble.push((text, color, background));
+                    }
+                }
+            }
+        }
 
-        let dimm_color = if self.no_dimm { WHITE } else { 243u16 };
+        let color = self.color;
+        let message_color = if context { theme.dimm } else { level_color };
 
-        let level = format!(" {} ", record.level);
-        let level_color = match record.level {
-            Level::Trace | Level::Verbose | Level::Debug | Level::None => dimm_color,
-            Level::Info => GREEN,
-            Level::Warn => YELLOW,
-            Level::Error | Level::Fatal | Level::Assert => RED,
+        // Lines of the message with the syntax color of each char
+        let pretty = if self.pretty.is_enabled() {
+            self.pretty.format(&record.message)
+        } else {
+            None
+        };
+        let lines = match pretty {
+            Some(lines) => lines
+                .iter()
+                .map(|line| {
+                    let mut text = String::new();
+                    let mut colors = vec![];
+                    for &(ref t, syntax) in line {
+                        let color = match syntax {
+                            _ if context => theme.dimm,
+                            Syntax::Key | Syntax::Text => message_color,
+                            Syntax::Literal | Syntax::Number => theme.highlight,
+                            Syntax::Punctuation => theme.dimm,
+                            Syntax::String => theme.text,
+                        };
+                        text.push_str(t);
+                        colors.extend(t.chars().map(|_| color));
+                    }
+                    (text, colors)
+                })
+                .collect(),
+            None => vec![(
+                record.message.clone(),
+                vec![message_color; record.message.chars().count()],
+            )],
         };
 
-        let color = self.color;
-        let highlight = self.highlight.iter().any(|r| r.is_match(&record.tag))
-            || self.highlight.iter().any(|r| r.is_match(&record.message));
-        let diff_width = self.diff_width;
-        let timestamp_width = self.date_format.1;
-
-        let paint = |term: &mut Box<::term::StdoutTerminal>,
-                     t: &str,
-                     fg: Color,
-                     bg: Option<Color>|
-         -> Result<(), Error> {
-            if let Some(bg) = bg {
-                term.bg(bg).map_err(|e| format_err!("{}", e))?;
-            }
-            term.fg(fg).map_err(|e| format_err!("{}", e))?;
-            write!(term, "{}", t)?;
-            if bg.is_some() {
-                term.reset()?;
+        let preamble_width = preamble
+            .iter()
+            .map(|&(ref t, _, _)| t.width())
+            .sum::<usize>() + 2;
+        // Windows terminal width reported is too big
+        #[cfg(target_os = "windows")]
+        let preamble_width = preamble_width + 1;
+
+        // Wrap the lines and attach the highlight and syntax color of each char
+        let mut chunks = vec![];
+        for &(ref text, ref syntax) in &lines {
+            let highlights = self.highlights.message(text);
+            let colors = highlights
+                .into_iter()
+                .zip(syntax.iter().cloned())
+                .collect::<Vec<(Option<Color>, Color)>>();
+            let wrapped = match terminal_width {
+                Some(columns) if preamble_width + text.width() > columns && columns > preamble_width => {
+                    wrap(text, columns - preamble_width)
+                }
+                _ => vec![text.as_str()],
+            };
+            let mut offset = 0;
+            for chunk in wrapped {
+                let len = chunk.chars().count();
+                chunks.push((chunk, colors[offset..offset + len].to_vec()));
+                offset += len;
             }
-            Ok(())
-        };
+        }
 
         let mut term = &mut self.term;
-        let mut print_msg = |chunk: &str, sign: &str| -> Result<(), Error> {
+        let mut print_msg = |chunk: &str, colors: &[(Option<Color>, Color)], sign: &str| -> Result<(), Error> {
             if color {
-                if highlight {
-                    term.attr(Attr::Bold)?;
+                for &(ref text, fg, bg) in &preamble {
+                    if let Some(bg) = bg {
+                        set_color(&mut term, bg, true)?;
+                    }
+                    set_color(&mut term, fg, false)?;
+                    write!(term, "{}", text)?;
+                    if bg.is_some() {
+                        term.reset()?;
+                    }
                 }
-                let timestamp_color = if highlight { YELLOW } else { DIMM_COLOR };
-                paint(
-                    &mut term,
-                    &format!(
-                        "{:<timestamp_width$}",
-                        timestamp,
-                        timestamp_width = timestamp_width
-                    ),
-                    timestamp_color,
-                    None,
-                )?;
-                write!(term, " ")?;
-                paint(
-                    &mut term,
-                    &format!("{:<tag_width$}", tag, tag_width = tag_width),
-                    hashed_color(&tag),
-                    None,
-                )?;
-                paint(&mut term, " (", DIMM_COLOR, None)?;
-                paint(&mut term, &format!("{}", pid), hashed_color(&pid), None)?;
-                write!(term, " ")?;
-                paint(&mut term, &format!("{}", tid), hashed_color(&tid), None)?;
-                paint(&mut term, ") ", DIMM_COLOR, None)?;
-                paint(&mut term, &format!("{}", level), BLACK, Some(level_color))?;
-                write!(term, " ")?;
-                paint(&mut term, &format!("{} {}", sign, chunk), level_color, None)?;
-                write!(term, "\n")?;
-                term.reset().map_err(|e| format_err!("{}", e))
-            } else {
-                writeln!(
-                    term,
-                    "{:<timestamp_width$} {:>diff_width$} {:>tag_width$} ({}{}) {} {} {}",
-                    timestamp,
-                    diff,
-                    tag,
-                    pid,
-                    tid,
-                    level,
-                    sign,
-                    chunk,
-                    timestamp_width = timestamp_width,
-                    diff_width = diff_width,
-                    tag_width = tag_width
-                ).map_err(|e| format_err!("{}", e))
-            }
-        };
-
-        if let Some(width) = terminal_width {
-            let preamble_width =
-                timestamp_width + 1 + self.diff_width + 1 + tag_width + 1 + 1 + self.process_width
-                    + if self.thread_width == 0 { 0 } else { 1 } + self.thread_width
-                    + 1 + 1 + 3 + 3;
-            // Windows terminal width reported is too big
-            #[cfg(target_os = "windows")]
-            let preamble_width = preamble_width + 1;
-
-            let record_len = record.message.chars().count();
-            let columns = width as usize;
-            if (preamble_width + record_len) > columns {
-                let mut m = record.message.clone();
-                // TODO: Refactor this!
-                while !m.is_empty() {
-                    let chars_left = m.chars().count();
-                    let (chunk_width, sign) = if chars_left == record_len {
-                        (columns - preamble_width, "┌")
-                    } else if chars_left <= (columns - preamble_width) {
-                        (chars_left, "└")
+                set_color(&mut term, message_color, false)?;
+                write!(term, "{} ", sign)?;
+                for (run, (highlight, fg)) in runs(chunk, colors) {
+                    if let Some(h) = highlight {
+                        set_color(&mut term, h, true)?;
+                        set_color(&mut term, theme.badge, false)?;
+                        write!(term, "{}", run)?;
+                        term.reset()?;
                     } else {
-                        (columns - preamble_width, "├")
-                    };
-
-                    let chunk: String = m.chars().take(chunk_width).collect();
-                    m = m.chars().skip(chunk_width).collect();
-                    print_msg(&chunk, sign)?;
+                        set_color(&mut term, fg, false)?;
+                        write!(term, "{}", run)?;
+                    }
                 }
+                // Reset before the newline to keep lines self-contained
+                term.reset()?;
+                writeln!(term).map_err(|e| format_err!("{}", e))
             } else {
-                print_msg(&record.message, " ")?;
+                for &(ref text, _, _) in &preamble {
+                    write!(term, "{}", text)?;
+                }
+                writeln!(term, "{} {}", sign, chunk).map_err(|e| format_err!("{}", e))
             }
-        } else {
-            print_msg(&record.message, " ")?;
         };
 
+        for (n, &(chunk, ref colors)) in chunks.iter().enumerate() {
+            let sign = if chunks.len() == 1 {
+                " "
+            } else if n == 0 {
+                "┌"
+            } else if n == chunks.len() - 1 {
+                "└"
+            } else {
+                "├"
+            };
+            print_msg(chunk, colors, sign)?;
+        }
+
         if self.time_diff {
             if let Some(ref ts) = record.timestamp {
                 if !record.tag.is_empty() {
@@ -358,7 +652,7 @@ impl<'a> Terminal {
     }
 }
 
-impl Drop for Terminal {
+impl<W: Write> Drop for Terminal<W> {
     fn drop(&mut self) {
         if self.color {
             self.term.reset().ok();
@@ -367,7 +661,7 @@ impl Drop for Terminal {
     }
 }
 
-impl Sink for Terminal {
+impl<W: Write> Sink for Terminal<W> {
     type SinkItem = Option<Record>;
     type SinkError = Error;
 
@@ -61,3 +61,117 @@ fn filter_message_regex() {
     assert!(output.0);
     assert_eq!(output.1.len(), 3);
 }
+
+#[test]
+fn filter_pid_tid() {
+    let input = svec!(
+        "03-01 02:19:45.207     1     2 I A: a",
+        "03-01 02:19:45.207     1     3 I B: b",
+        "03-01 02:19:45.207    10    11 I C: c",
+        "unparseable"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--pid", "1"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output = run_rogcat_with_input_file(&svec!("--pid", "1", "--pid", "10"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 3);
+
+    let output = run_rogcat_with_input_file(&svec!("--pid", "1", "--tid", "3"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 1);
+}
+
+#[test]
+fn filter_package() {
+    let input = svec!(
+        "03-01 02:19:45.207   100   100 I A: not started yet",
+        "03-01 02:19:45.207   500   510 I ActivityManager: Start proc 100:com.example/u0a12 for activity com.example/.Main",
+        "03-01 02:19:45.207   100   100 I A: started",
+        "03-01 02:19:45.207   101   101 I B: other",
+        "03-01 02:19:45.207   500   510 I ActivityManager: Start proc 101:com.example:remote/u0a12 for service com.example/.Remote",
+        "03-01 02:19:45.207   101   101 I B: remote"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--package", "com.example"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output = run_rogcat_with_input_file(&svec!("--uid", "10012"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+}
+
+#[test]
+fn filter_since_until() {
+    let input = svec!(
+        "03-01 02:19:45.207     1     2 I A: a",
+        "03-01 02:20:45.207     1     2 I A: b",
+        "03-01 02:21:45.207     1     2 I A: c",
+        "continuation of c",
+        "03-01 02:22:45.207     1     2 I A: d"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--since", "02:20"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 4);
+
+    let output = run_rogcat_with_input_file(&svec!("--until", "03-01 02:21"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output =
+        run_rogcat_with_input_file(&svec!("--since", "02:20", "--until", "2m"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 3);
+
+    let output = run_rogcat_with_input_file(&svec!("--since", "yesterday"), &input).unwrap();
+    assert!(!output.0);
+
+    // Reading continues after midnight if the bound has no date
+    let input = svec!(
+        "02-28 23:50:45.207     1     2 I A: a",
+        "03-01 00:10:45.207     1     2 I A: b",
+        "03-01 00:40:45.207     1     2 I A: c"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--until", "00:30", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1, svec!("03-01 00:10:45.207     1     2 I A: b"));
+}
+
+#[test]
+fn filter_context() {
+    let input = svec!("a", "b", "X1", "c", "d", "e", "X2", "f");
+    let output = run_rogcat_with_input_file(&svec!("-m", "X", "-C", "1", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1, svec!("b", "X1", "c", "--", "e", "X2", "f"));
+
+    let output = run_rogcat_with_input_file(&svec!("-m", "X", "-B", "2", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1, svec!("a", "b", "X1", "--", "d", "e", "X2"));
+
+    let output = run_rogcat_with_input_file(&svec!("-m", "X", "-A", "3", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1, svec!("X1", "c", "d", "e", "X2", "f"));
+}
+
+#[test]
+fn filter_filterspec() {
+    let input = svec!(
+        "03-01 02:19:45.207     1     2 D ActivityManager: a",
+        "03-01 02:19:45.207     1     2 I ActivityManager: b",
+        "03-01 02:19:45.207     1     2 V MyApp: c",
+        "03-01 02:19:45.207     1     2 E Other: d"
+    );
+    let output =
+        run_rogcat_with_input_file(&svec!("--filterspec", "ActivityManager:I MyApp:V *:S"), &input)
+            .unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output = run_rogcat_with_input_file(&svec!("-F", "ActivityManager:I"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 3);
+
+    let output = run_rogcat_with_input_file(&svec!("-F", "*:Q"), &input).unwrap();
+    assert!(!output.0);
+}
@@ -13,3 +13,354 @@ fn head() {
     assert!(output.0);
     assert_eq!(output.1.len(), 2);
 }
+
+#[test]
+fn dedup() {
+    let input = svec!("A", "A", "A", "B", "A");
+    let output = run_rogcat_with_input_file(&svec!("--dedup", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(
+        output.1,
+        svec!("A", "Last message repeated 2 times", "B", "A")
+    );
+
+    let input = svec!("A", "A", "A");
+    let output = run_rogcat_with_input_file(&svec!("--dedup", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1, svec!("A", "Last message repeated 2 times"));
+}
+
+#[test]
+fn rate_limit() {
+    let input = svec!(
+        "03-01 02:19:45.100     1     2 I A: a",
+        "03-01 02:19:45.200     1     2 I A: b",
+        "03-01 02:19:45.300     1     2 I A: c",
+        "03-01 02:19:45.400     1     2 I B: a",
+        "03-01 02:19:46.400     1     2 I A: d"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--rate-limit", "2", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 5);
+    assert_eq!(
+        output.1[3],
+        "Dropped 1 record exceeding 2 records per second"
+    );
+
+    // The summary is emitted as soon as the window expired even if the tag is silent
+    let input = svec!(
+        "03-01 02:19:45.100     1     2 I A: a",
+        "03-01 02:19:45.200     1     2 I A: b",
+        "03-01 02:19:45.300     1     2 I A: c",
+        "03-01 02:19:45.400     1     2 I A: d",
+        "03-01 02:19:46.400     1     2 I B: a",
+        "03-01 02:19:46.500     1     2 I B: b"
+    );
+    let output = run_rogcat_with_input_file(&svec!("--rate-limit", "2", "-f", "raw"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(
+        output.1,
+        svec!(
+            "03-01 02:19:45.100     1     2 I A: a",
+            "03-01 02:19:45.200     1     2 I A: b",
+            "Dropped 2 records exceeding 2 records per second",
+            "03-01 02:19:46.400     1     2 I B: a",
+            "03-01 02:19:46.500     1     2 I B: b"
+        )
+    );
+}
+
+#[test]
+fn exit_on() {
+    let input = svec!("A", "boot completed", "C");
+    let output = run_rogcat_with_input_file(&svec!("--exit-on", "boot"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output = run_rogcat_with_input_file(&svec!("--exit-on", "nope"), &input).unwrap();
+    assert!(!output.0);
+    assert_eq!(output.1.len(), 3);
+}
+
+#[test]
+fn fail_on() {
+    let input = svec!("A", "FATAL EXCEPTION", "C");
+    let output = run_rogcat_with_input_file(&svec!("--fail-on", "^FATAL"), &input).unwrap();
+    assert!(!output.0);
+    assert_eq!(output.1.len(), 2);
+
+    let output =
+        run_rogcat_with_input_file(&svec!("--fail-on", "^FATAL", "--fail-late"), &input).unwrap();
+    assert!(!output.0);
+    assert_eq!(output.1.len(), 3);
+
+    let output = run_rogcat_with_input_file(&svec!("--fail-on", "fatal"), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 3);
+}
+
+#[test]
+#[cfg(not(windows))]
+fn trigger() {
+    let file = tempfile().unwrap();
+    let command = format!("echo \"$ROGCAT_MESSAGE\" {{{{message}}}} >> {}", file.display());
+    let input = svec!("A", "FATAL EXCEPTION", "C", "FATAL EXCEPTION");
+    let output = run_rogcat_with_input_file(&svec!("--trigger", "^FATAL", command), &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 4);
+    // The second match is within the trigger interval
+    assert!(check_file_content(&file, &svec!("FATAL EXCEPTION FATAL EXCEPTION")).unwrap());
+
+    // Record text is not interpreted by the shell
+    let file = tempfile().unwrap();
+    let command = format!("echo {{{{message}}}} >> {}", file.display());
+    let input = svec!("FATAL '; echo $(id) & `id` | \"x\"");
+    let output = run_rogcat_with_input_file(&svec!("--trigger", "^FATAL", command), &input).unwrap();
+    assert!(output.0);
+    assert!(check_file_content(&file, &input).unwrap());
+}
+
+#[test]
+fn tui_conflicts_with_markers() {
+    // Both read keys from the tty
+    let output = run_rogcat_with_input_file(&svec!("--tui", "--markers"), &svec!("A")).unwrap();
+    assert!(!output.0);
+}
+
+#[test]
+fn expect() {
+    let script = tempfile_with_content(&svec!(
+        "[[step]]",
+        "message = \"^A\"",
+        "[[step]]",
+        "message = \"^C\"",
+        "[[never]]",
+        "message = \"FATAL\""
+    )).unwrap();
+    let script = format!("{}", script.display());
+    let run_with = |input: SVec, args: SVec| {
+        let input = tempfile_with_content(&input).unwrap();
+        let mut a = svec!("expect", script.clone(), "-i", format!("{}", input.display()));
+        a.extend(args);
+        run_rogcat(&a, None).unwrap()
+    };
+    let run = |input: SVec| run_with(input, svec!());
+
+    let output = run(svec!("A", "B", "C"));
+    assert!(output.0);
+    assert_eq!(output.1.last().unwrap(), "Result: PASS");
+
+    let output = run(svec!("A", "B"));
+    assert!(!output.0);
+    assert_eq!(output.1.last().unwrap(), "Result: FAIL");
+
+    let output = run(svec!("A", "FATAL", "C"));
+    assert!(!output.0);
+    assert_eq!(output.1[2], "Never message \"FATAL\": FAIL: FATAL");
+
+    // Never patterns are checked until the input ends
+    let output = run(svec!("A", "C", "FATAL"));
+    assert!(!output.0);
+    assert_eq!(output.1[2], "Never message \"FATAL\": FAIL: FATAL");
+
+    // Filters apply before the script
+    let output = run_with(svec!("A", "FATAL", "C"), svec!("-m", "!FATAL"));
+    assert!(output.0);
+    assert_eq!(output.1.last().unwrap(), "Result: PASS");
+}
+
+#[test]
+fn flight_recorder() {
+    let dir = tempdir().unwrap();
+    let file = dir.join("dump.log");
+    let input = svec!("A", "B", "C", "FATAL", "D", "E", "F", "FATAL", "G");
+    let args = svec!(
+        "--flight-recorder",
+        "2",
+        "--dump-on",
+        "^FATAL",
+        "--dump-after",
+        "1",
+        "--dump-file",
+        format!("{}", file.display()),
+        "-a",
+        "enumerate"
+    );
+    let output = run_rogcat_with_input_file(&args, &input).unwrap();
+    assert!(output.0);
+    assert_eq!(output.1.len(), 9);
+    assert!(check_file_content(&dir.join("dump-000.log"), &svec!("C", "FATAL", "D")).unwrap());
+    assert!(check_file_content(&dir.join("dump-001.log"), &svec!("F", "FATAL", "G")).unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn flight_recorder_signal() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = tempdir().unwrap();
+    let file = dir.join("dump.log");
+    let mut process = Command::new(find_rogcat_binary())
+        .args(&["-f", "raw", "--flight-recorder", "10", "--dump-after", "0", "-a", "enumerate"])
+        .arg("--dump-file")
+        .arg(&file)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = process.stdin.take().unwrap();
+    let mut stdout = BufReader::new(process.stdout.take().unwrap());
+    let mut stderr = BufReader::new(process.stderr.take().unwrap());
+    let mut line = String::new();
+
+    // Wait until the records pass the recorder
+    stdin.write_all(b"A\nB\n").unwrap();
+    for expected in &["A\n", "B\n"] {
+        line.clear();
+        stdout.read_line(&mut line).unwrap();
+        assert_eq!(line, *expected);
+    }
+    assert!(
+        Command::new("kill")
+            .arg("-USR1")
+            .arg(process.id().to_string())
+            .status()
+            .unwrap()
+            .success()
+    );
+    line.clear();
+    stderr.read_line(&mut line).unwrap();
+    assert!(line.starts_with("Flight recorder dumped to"));
+
+    stdin.write_all(b"C\n").unwrap();
+    drop(stdin);
+    let rest = stdout.lines().map(|l| l.unwrap()).collect::<SVec>();
+    assert!(process.wait().unwrap().success());
+    // The dump does not add records e.g markers to the output
+    assert_eq!(rest, svec!("C"));
+    assert!(check_file_content(&dir.join("dump-000.log"), &svec!("A", "B")).unwrap());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn pretty_config_in_human_file() {
+    use std::fs::{create_dir_all, File};
+    use std::io::Write;
+    use std::process::Command;
+
+    let config = tempdir().unwrap();
+    create_dir_all(config.join("rogcat")).unwrap();
+    File::create(config.join("rogcat").join("config.toml"))
+        .unwrap()
+        .write_all(b"terminal_pretty = [\"json\", \"kv\"]\n")
+        .unwrap();
+    let input = tempfile_with_content(&svec!("03-01 02:19:45.100     1     2 I A: {\"a\": [1, 2]}")).unwrap();
+    let dir = tempdir().unwrap();
+    let output = dir.join("out.log");
+    let status = Command::new(find_rogcat_binary())
+        .env("XDG_CONFIG_HOME", &config)
+        .arg("-i")
+        .arg(&input)
+        .args(&["--format", "human", "--color", "never", "--hide-timestamp", "-o"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let content = file_content(&output).unwrap();
+    assert_eq!(content.len(), 1);
+    assert!(content[0].ends_with(" {\"a\": [1, 2]}"));
+}
+
+#[test]
+fn human_file() {
+    let input = svec!(
+        "03-01 02:19:45.100   123   456 I ActivityManager: Start proc com.example for activity with a long message that wraps",
+        "03-01 02:19:45.200   123   456 E Tag: short"
+    );
+    let expected = svec!(
+        "02:19:45.100 ActivityManager (123 456)  I  ┌ Start proc com.",
+        "02:19:45.100 ActivityManager (123 456)  I  ├ example for act",
+        "02:19:45.100 ActivityManager (123 456)  I  ├ ivity with a lo",
+        "02:19:45.100 ActivityManager (123 456)  I  ├ ng message that",
+        "02:19:45.100 ActivityManager (123 456)  I  └  wraps",
+        "02:19:45.200             Tag (123 456)  E    short"
+    );
+    let dir = tempdir().unwrap();
+
+    let output = dir.join("plain.log");
+    let args = svec!("--format", "human", "--color", "never", "--width", "60", "-o", format!("{}", output.display()));
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    assert_eq!(file_content(&output).unwrap(), expected);
+
+    // Colors are escape sequences around the same text regardless of TERM
+    let output = dir.join("color.log");
+    let args = svec!("--format", "human", "--color", "always", "--width", "60", "-o", format!("{}", output.display()));
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    let content = file_content(&output).unwrap();
+    assert!(content.iter().all(|l| l.contains("\x1b[") && l.ends_with("\x1b[0m")));
+    let escapes = ::regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    let content = content
+        .iter()
+        .map(|l| escapes.replace_all(l, "").into_owned())
+        .collect::<SVec>();
+    assert_eq!(content, expected);
+}
+
+#[test]
+fn file_size_rotation() {
+    let input = (0..10).map(|i| format!("record {:03}", i)).collect::<SVec>();
+    let dir = tempdir().unwrap();
+    let output = dir.join("out.log");
+    let args = svec!("--file-size", "30", "-o", format!("{}", output.display()));
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    // Three records of 11 bytes fill a file
+    assert_eq!(::std::fs::read_dir(&dir).unwrap().count(), 4);
+    assert!(check_file_content(&dir.join("out-000.log"), &input[..3].to_vec()).unwrap());
+    assert!(check_file_content(&dir.join("out-003.log"), &input[9..].to_vec()).unwrap());
+}
+
+#[test]
+fn compress_and_keep_files() {
+    let input = (0..10).map(|i| format!("record {:03}", i)).collect::<SVec>();
+    let dir = tempdir().unwrap();
+    let output = dir.join("out.log");
+    let args = svec!("--file-size", "30", "--compress", "--keep-files", "2", "-o", format!("{}", output.display()));
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    let mut files = ::std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<String>>();
+    files.sort();
+    assert_eq!(files, vec!["out-002.log.gz", "out-003.log.gz"]);
+}
+
+#[test]
+fn append() {
+    let input = svec!("a", "b");
+    let dir = tempdir().unwrap();
+    let output = format!("{}", dir.join("out.log").display());
+    assert!(run_rogcat_with_input_file(&svec!("-o", output.clone()), &input).unwrap().0);
+    assert!(!run_rogcat_with_input_file(&svec!("-o", output.clone()), &input).unwrap().0);
+    let args = svec!("--append", "--fsync", "-o", output.clone());
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    assert!(check_file_content(&dir.join("out.log"), &svec!("a", "b", "a", "b")).unwrap());
+}
+
+#[test]
+fn html_index() {
+    let input = (0..5).map(|i| format!("record {:03}", i)).collect::<SVec>();
+    let dir = tempdir().unwrap();
+    let output = dir.join("out.html");
+    let args = svec!("-f", "html", "-n", "2", "-o", format!("{}", output.display()));
+    assert!(run_rogcat_with_input_file(&args, &input).unwrap().0);
+    let index = file_content(&dir.join("out-index.html")).unwrap();
+    for page in &["out-000.html", "out-001.html", "out-002.html"] {
+        assert!(index.iter().any(|l| l.contains(&format!("href=\"{}\"", page))));
+    }
+    let page = file_content(&dir.join("out-002.html")).unwrap();
+    assert!(page.iter().any(|l| l.contains("record 004")));
+    assert_eq!(page.last().unwrap(), "</html>");
+}
@@ -23,6 +23,14 @@ message = [\"C\"]
 extends = [\"AB\"]
 highlight = [\"A\"]
 
+[profile.Fixed]
+fixed_strings = [\"message\"]
+ignore_case = [\"message\"]
+message = [\"a.b\"]
+
+[profile.InvalidKind]
+ignore_case = [\"thread\"]
+
 # CicleA extends CircleB and CircleB extends CircleA -> invalid
 [profile.CircleA]
 extends = [\"CircleB\"]
@@ -73,11 +81,11 @@ fn list_profiles() {
     assert!(output.1.len() >= 1); // check for >1 if default location settings are found
 
     let output = run_rogcat_with_config_and_input_file(&svec!("profiles", "-l"), &vec![]).unwrap();
-    assert_eq!(output.len(), 7);
+    assert_eq!(output.len(), 9);
 
     let output =
         run_rogcat_with_config_and_input_file(&svec!("profiles", "--list"), &vec![]).unwrap();
-    assert_eq!(output.len(), 7);
+    assert_eq!(output.len(), 9);
 }
 
 #[test]
@@ -111,6 +119,19 @@ fn extends_message_a_b_c() {
     assert_eq!(output.len(), 3);
 }
 
+#[test]
+fn fixed_strings_ignore_case() {
+    let input = svec!("A.B", "axb", "xa.bx");
+    let output = run_rogcat_with_config_and_input_file(&svec!("-p", "Fixed", "-f", "raw"), &input).unwrap();
+    assert_eq!(output, svec!("A.B", "xa.bx"));
+
+    let lines = CONFIG.lines().map(|s| s.to_string()).collect();
+    let config = tempfile_with_content(&lines).unwrap().display().to_string();
+    let args = svec!("--profiles-path", config, "-p", "InvalidKind");
+    let output = run_rogcat(&args, None).unwrap();
+    assert!(!output.0);
+}
+
 #[test]
 fn extends_circle() {
     let lines = CONFIG.lines().map(|s| s.to_string()).collect();
@@ -0,0 +1,407 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use config_get;
+use failure::Error;
+use record::Level;
+use serde::de::{self, Deserialize, Deserializer, Unexpected, Visitor};
+use std::collections::HashMap;
+use std::fmt::Formatter;
+use std::result::Result as StdResult;
+use std::str::FromStr;
+use term::color;
+
+/// A color of the 256 color palette or a 24 bit truecolor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Indexed(color::Color),
+    Rgb(u8, u8, u8),
+}
+
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Color, Error> {
+        let index = match s.to_lowercase().as_str() {
+            "black" => Some(color::BLACK),
+            "red" => Some(color::RED),
+            "green" => Some(color::GREEN),
+            "yellow" => Some(color::YELLOW),
+            "blue" => Some(color::BLUE),
+            "magenta" => Some(color::MAGENTA),
+            "cyan" => Some(color::CYAN),
+            "white" => Some(color::WHITE),
+            "bright_black" => Some(color::BRIGHT_BLACK),
+            "bright_red" => Some(color::BRIGHT_RED),
+            "bright_green" => Some(color::BRIGHT_GREEN),
+            "bright_yellow" => Some(color::BRIGHT_YELLOW),
+            "bright_blue" => Some(color::BRIGHT_BLUE),
+            "bright_magenta" => Some(color::BRIGHT_MAGENTA),
+            "bright_cyan" => Some(color::BRIGHT_CYAN),
+            "bright_white" => Some(color::BRIGHT_WHITE),
+            s => s.parse::<u8>().ok().map(color::Color::from),
+        };
+        if let Some(index) = index {
+            return Ok(Color::Indexed(index));
+        }
+
+        if s.len() == 7 && s.is_ascii() && s.starts_with('#') {
+            let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(1), channel(3), channel(5)) {
+                return Ok(Color::Rgb(r, g, b));
+            }
+        }
+        Err(format_err!("Invalid color: {}", s))
+    }
+}
+
+impl Color {
+    /// Approximate 24 bit value of palette colors
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        const BASIC: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+        let level = |c: u16| if c == 0 { 0 } else { (55 + c * 40) as u8 };
+        match *self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(c @ 0...15) => BASIC[c as usize],
+            Color::Indexed(c @ 16...231) => {
+                let c = c - 16;
+                (level(c / 36), level(c / 6 % 6), level(c % 6))
+            }
+            Color::Indexed(c) => {
+                let gray = (8 + (c.min(255) - 232) * 10) as u8;
+                (gray, gray, gray)
+            }
+        }
+    }
+
+    /// CSS notation
+    pub fn css(&self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    /// ANSI escape sequence that sets this color as foreground
+    pub fn ansi(&self) -> String {
+        match *self {
+            Color::Indexed(c) if c < 8 => format!("\x1b[{}m", 30 + c),
+            Color::Indexed(c) if c < 16 => format!("\x1b[{}m", 90 + c - 8),
+            Color::Indexed(c) => format!("\x1b[38;5;{}m", c),
+            Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ColorVisitor;
+
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = Color;
+
+            fn visit_str<E>(self, s: &str) -> StdResult<Color, E>
+            where
+                E: de::Error,
+            {
+                Color::from_str(s).map_err(|_| de::Error::invalid_value(Unexpected::Str(s), &self))
+            }
+
+            fn visit_i64<E>(self, i: i64) -> StdResult<Color, E>
+            where
+                E: de::Error,
+            {
+                if i >= 0 && i <= 255 {
+                    Ok(Color::Indexed(i as color::Color))
+                } else {
+                    Err(de::Error::invalid_value(Unexpected::Signed(i), &self))
+                }
+            }
+
+            fn visit_u64<E>(self, u: u64) -> StdResult<Color, E>
+            where
+                E: de::Error,
+            {
+                if u <= 255 {
+                    Ok(Color::Indexed(u as color::Color))
+                } else {
+                    Err(de::Error::invalid_value(Unexpected::Unsigned(u), &self))
+                }
+            }
+
+            fn expecting(&self, formatter: &mut Formatter) -> ::std::fmt::Result {
+                formatter.write_str("color name, palette index or #rrggbb")
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod palette {
+    pub const DARK_DIMM: u16 = 243;
+    pub const LIGHT_DIMM: u16 = 242;
+    pub const LIGHT_ERROR: u16 = 160;
+    pub const LIGHT_HIGHLIGHT: u16 = 130;
+    pub const LIGHT_INFO: u16 = 28;
+    pub const LIGHT_WARN: u16 = 166;
+}
+
+/// The windows console supports 16 colors only
+#[cfg(target_os = "windows")]
+mod palette {
+    use term::color::*;
+    pub const DARK_DIMM: u16 = WHITE;
+    pub const LIGHT_DIMM: u16 = BRIGHT_BLACK;
+    pub const LIGHT_ERROR: u16 = RED;
+    pub const LIGHT_HIGHLIGHT: u16 = MAGENTA;
+    pub const LIGHT_INFO: u16 = GREEN;
+    pub const LIGHT_WARN: u16 = MAGENTA;
+}
+
+/// Background the hashed colors are picked for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Background {
+    Dark,
+    Light,
+}
+
+/// Custom theme as configured in `config.toml`. Unset colors are taken
+/// from the builtin theme selected by `base`.
+#[derive(Debug, Default, Deserialize)]
+struct ThemeFile {
+    base: Option<String>,
+    badge: Option<Color>,
+    debug: Option<Color>,
+    dimm: Option<Color>,
+    error: Option<Color>,
+    highlight: Option<Color>,
+    info: Option<Color>,
+    text: Option<Color>,
+    warn: Option<Color>,
+}
+
+/// Tag or process with a fixed color
+#[derive(Debug, Deserialize)]
+struct PinFile {
+    color: Color,
+    process: Option<String>,
+    tag: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    background: Background,
+    /// Foreground of the level column and highlights
+    pub badge: Color,
+    debug: Color,
+    /// Separators, context records and decorations
+    pub dimm: Color,
+    error: Color,
+    /// Default color of highlight patterns
+    pub highlight: Color,
+    info: Color,
+    processes: HashMap<String, Color>,
+    tags: HashMap<String, Color>,
+    /// Regular text
+    pub text: Color,
+    warn: Color,
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            background: Background::Dark,
+            badge: Color::Indexed(color::BLACK),
+            debug: Color::Indexed(palette::DARK_DIMM),
+            dimm: Color::Indexed(palette::DARK_DIMM),
+            error: Color::Indexed(color::RED),
+            highlight: Color::Indexed(color::YELLOW),
+            info: Color::Indexed(color::GREEN),
+            processes: HashMap::new(),
+            tags: HashMap::new(),
+            text: Color::Indexed(color::WHITE),
+            warn: Color::Indexed(color::YELLOW),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            background: Background::Light,
+            badge: Color::Indexed(color::WHITE),
+            debug: Color::Indexed(palette::LIGHT_DIMM),
+            dimm: Color::Indexed(palette::LIGHT_DIMM),
+            error: Color::Indexed(palette::LIGHT_ERROR),
+            highlight: Color::Indexed(palette::LIGHT_HIGHLIGHT),
+            info: Color::Indexed(palette::LIGHT_INFO),
+            processes: HashMap::new(),
+            tags: HashMap::new(),
+            text: Color::Indexed(color::BLACK),
+            warn: Color::Indexed(palette::LIGHT_WARN),
+        }
+    }
+
+    /// Builtin or custom theme `name`
+    fn named(name: &str) -> Result<Theme, Error> {
+        match name {
+            "dark" => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            _ => {
+                let file = config_get::<ThemeFile>(&format!("terminal_themes.{}", name))
+                    .ok_or_else(|| format_err!("Unknown or invalid theme: {}", name))?;
+                let base = match file.base.as_ref().map(|b| b.as_str()) {
+                    Some("light") => Theme::light(),
+                    Some("dark") | None => Theme::dark(),
+                    Some(b) => return Err(format_err!("Invalid base of theme {}: {}", name, b)),
+                };
+                Ok(Theme {
+                    badge: file.badge.unwrap_or(base.badge),
+                    debug: file.debug.or(file.dimm).unwrap_or(base.debug),
+                    dimm: file.dimm.unwrap_or(base.dimm),
+                    error: file.error.unwrap_or(base.error),
+                    highlight: file.highlight.unwrap_or(base.highlight),
+                    info: file.info.unwrap_or(base.info),
+                    text: file.text.unwrap_or(base.text),
+                    warn: file.warn.unwrap_or(base.warn),
+                    ..base
+                })
+            }
+        }
+    }
+
+    /// Theme selected by `terminal_theme` including the pinned colors
+    pub fn new(no_dimm: bool) -> Result<Theme, Error> {
+        let name = config_get::<String>("terminal_theme").unwrap_or_else(|| "dark".to_owned());
+        let mut theme = Theme::named(&name.to_lowercase())?;
+
+        for pin in config_get::<Vec<PinFile>>("terminal_pinned").unwrap_or_default() {
+            if let Some(tag) = pin.tag {
+                theme.tags.insert(tag, pin.color);
+            }
+            if let Some(process) = pin.process {
+                theme.processes.insert(process, pin.color);
+            }
+        }
+
+        if no_dimm {
+            theme.debug = theme.text;
+        }
+        Ok(theme)
+    }
+
+    pub fn level(&self, level: &Level) -> Color {
+        match *level {
+            Level::Trace | Level::Verbose | Level::Debug | Level::None => self.debug,
+            Level::Info => self.info,
+            Level::Warn => self.warn,
+            Level::Error | Level::Fatal | Level::Assert => self.error,
+        }
+    }
+
+    /// Pinned color of `tag` or a color derived from `hash`
+    pub fn tag(&self, tag: &str, hash: &str) -> Color {
+        self.tags
+            .get(tag)
+            .cloned()
+            .unwrap_or_else(|| self.hashed(hash))
+    }
+
+    /// Pinned color of `process` or a color derived from `hash`
+    pub fn process(&self, process: &str, hash: &str) -> Color {
+        self.processes
+            .get(process)
+            .cloned()
+            .unwrap_or_else(|| self.hashed(hash))
+    }
+
+    #[cfg(target_os = "windows")]
+    fn hashed(&self, i: &str) -> Color {
+        let c = i.bytes().fold(42u16, |c, x| c ^ u16::from(x));
+        Color::Indexed(match self.background {
+            Background::Dark => c % 15 + 1,
+            Background::Light => [1, 2, 4, 5, 6, 9, 12, 13][c as usize % 8],
+        })
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn hashed(&self, i: &str) -> Color {
+        let c = i.bytes().fold(42u16, |c, x| c ^ u16::from(x));
+        Color::Indexed(match self.background {
+            // Some colors are hard to read on (at least) dark terminals
+            // and others seem just ugly to me...
+            Background::Dark => match c {
+                c @ 0...1 => c + 2,
+                c @ 16...21 => c + 6,
+                c @ 52...55 | c @ 126...129 => c + 4,
+                c @ 163...165 | c @ 200...201 => c + 3,
+                c @ 207 => c + 1,
+                c @ 232...240 => c + 9,
+                c => c,
+            },
+            // Avoid the bright half of the palette on light terminals
+            Background::Light => match c {
+                c @ 0...15 => [1, 2, 4, 5, 6, 9, 12, 13][c as usize % 8],
+                c @ 16...231 => {
+                    let (r, g, b) = ((c - 16) / 36, (c - 16) / 6 % 6, (c - 16) % 6);
+                    if r + g + b > 8 || g > 3 {
+                        16 + 36 * (r * 3 / 5) + 6 * (g * 3 / 5) + b * 3 / 5
+                    } else {
+                        c
+                    }
+                }
+                c @ 244...255 => c - 12,
+                c => c,
+            },
+        })
+    }
+}
+
+#[test]
+fn parse_color() {
+    assert_eq!(Color::from_str("red").unwrap(), Color::Indexed(color::RED));
+    assert_eq!(Color::from_str("243").unwrap(), Color::Indexed(243));
+    assert_eq!(Color::from_str("#ff8000").unwrap(), Color::Rgb(255, 128, 0));
+    assert!(Color::from_str("#ff80").is_err());
+    assert!(Color::from_str("#1ä234").is_err());
+    assert!(Color::from_str("256").is_err());
+    assert!(Color::from_str("rosa").is_err());
+}
+
+#[test]
+fn css_color() {
+    assert_eq!(Color::Indexed(color::RED).css(), "#cd0000");
+    assert_eq!(Color::Indexed(208).css(), "#ff8700");
+    assert_eq!(Color::Indexed(243).css(), "#767676");
+    assert_eq!(Color::Rgb(1, 2, 3).css(), "#010203");
+}
+
+#[test]
+fn pinned_colors() {
+    let mut theme = Theme::light();
+    theme.tags.insert("ActivityManager".to_owned(), Color::Rgb(1, 2, 3));
+    assert_eq!(theme.tag("ActivityManager", "  ActivityManager"), Color::Rgb(1, 2, 3));
+    assert_eq!(theme.tag("Foo", "Foo"), theme.hashed("Foo"));
+    assert_eq!(theme.level(&Level::Info), theme.info);
+}
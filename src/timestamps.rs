@@ -0,0 +1,176 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use config_get;
+use failure::Error;
+use record::Timestamp;
+use std::str::FromStr;
+use time::{self, at, at_utc, now, Duration, Timespec, Tm};
+
+pub const MODES: &[&str] = &["absolute", "delta", "epoch", "local", "relative", "utc"];
+
+/// How record timestamps are displayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Device time as logged
+    Absolute,
+    /// Seconds since the previous record
+    Delta,
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    Epoch,
+    /// Device time converted to the time zone of the host
+    Local,
+    /// Seconds since the first record
+    Relative,
+    /// Device time converted to UTC
+    Utc,
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Mode, Error> {
+        match s {
+            "absolute" => Ok(Mode::Absolute),
+            "delta" => Ok(Mode::Delta),
+            "epoch" => Ok(Mode::Epoch),
+            "local" => Ok(Mode::Local),
+            "relative" => Ok(Mode::Relative),
+            "utc" => Ok(Mode::Utc),
+            _ => Err(format_err!("Invalid time mode: {}", s)),
+        }
+    }
+}
+
+/// Formats timestamps according to a `Mode` and keeps track of the
+/// first and previous record for relative and delta times
+#[derive(Clone, Debug)]
+pub struct Timestamps {
+    mode: Mode,
+    first: Option<Timespec>,
+    previous: Option<Timespec>,
+}
+
+impl Timestamps {
+    pub fn new<'a>(args: &ArgMatches<'a>) -> Result<Timestamps, Error> {
+        let mode = match args.value_of("time")
+            .map(|t| t.to_owned())
+            .or_else(|| config_get("time"))
+        {
+            Some(mode) => Mode::from_str(&mode)?,
+            None => Mode::Absolute,
+        };
+        Ok(Timestamps::with_mode(mode))
+    }
+
+    pub fn with_mode(mode: Mode) -> Timestamps {
+        Timestamps {
+            mode,
+            first: None,
+            previous: None,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Format `timestamp`. Absolute, local and UTC times are formatted
+    /// with the strftime `format`.
+    pub fn format(&mut self, timestamp: &Timestamp, format: &str) -> String {
+        if self.mode == Mode::Absolute {
+            return strftime(format, timestamp);
+        }
+
+        let instant = instant(timestamp);
+        let first = *self.first.get_or_insert(instant);
+        let previous = self.previous.unwrap_or(instant);
+        self.previous = Some(instant);
+
+        match self.mode {
+            Mode::Absolute => unreachable!(),
+            Mode::Delta => seconds(instant - previous),
+            Mode::Epoch => seconds(instant - Timespec::new(0, 0)),
+            Mode::Local => strftime(format, &at(instant)),
+            Mode::Relative => seconds(instant - first),
+            Mode::Utc => strftime(format, &at_utc(instant)),
+        }
+    }
+}
+
+/// Format `tm` like `time::strftime` with `%3f` for milliseconds
+pub fn strftime(format: &str, tm: &Tm) -> String {
+    let format = format.replace("%3f", &format!("{:03}", tm.tm_nsec / 1_000_000));
+    time::strftime(&format, tm).unwrap_or_default()
+}
+
+/// Point in time of `timestamp`. Records without a year are assumed to be
+/// from the current year and records without a UTC offset to be in the
+/// time zone of the host.
+fn instant(timestamp: &Timestamp) -> Timespec {
+    let mut tm: Tm = **timestamp;
+    // The parser stores the full year
+    tm.tm_year = if tm.tm_year > 0 {
+        tm.tm_year - 1900
+    } else {
+        now().tm_year
+    };
+    tm.tm_utcoff = 0;
+    let utc = tm.to_timespec();
+    let offset = timestamp.utc_offset.unwrap_or_else(|| at(utc).tm_utcoff);
+    utc - Duration::seconds(i64::from(offset))
+}
+
+/// Format `duration` as seconds with millisecond precision
+fn seconds(duration: Duration) -> String {
+    let millis = duration.num_milliseconds();
+    let sign = if millis < 0 { "-" } else { "" };
+    let millis = millis.abs();
+    format!("{}{}.{:03}", sign, millis / 1000, millis % 1000)
+}
+
+#[cfg(test)]
+fn timestamp(s: &str, utcoff: i32) -> Timestamp {
+    let mut tm = ::time::strptime(s, "%Y-%m-%d %H:%M:%S.%f").unwrap();
+    tm.tm_year += 1900;
+    tm.tm_utcoff = utcoff;
+    Timestamp::with_utc_offset(tm)
+}
+
+#[test]
+fn time_modes() {
+    let a = timestamp("2018-01-01 12:00:00.250", 3600);
+    let b = timestamp("2018-01-01 12:00:01.500", 3600);
+    let c = timestamp("2018-01-01 12:00:01.000", 3600);
+
+    let mut t = Timestamps::with_mode(Mode::Relative);
+    assert_eq!(t.format(&a, ""), "0.000");
+    assert_eq!(t.format(&b, ""), "1.250");
+    assert_eq!(t.format(&c, ""), "0.750");
+
+    let mut t = Timestamps::with_mode(Mode::Delta);
+    assert_eq!(t.format(&a, ""), "0.000");
+    assert_eq!(t.format(&b, ""), "1.250");
+    assert_eq!(t.format(&c, ""), "-0.500");
+
+    let mut t = Timestamps::with_mode(Mode::Epoch);
+    assert_eq!(t.format(&a, ""), "1514804400.250");
+
+    let mut t = Timestamps::with_mode(Mode::Utc);
+    assert_eq!(t.format(&a, "%m-%d %H:%M:%S"), "01-01 11:00:00");
+    assert_eq!(t.format(&b, "%S.%3f"), "01.500");
+
+    // An explicit offset of zero is not the time zone of the host
+    let utc = timestamp("2018-01-01 12:00:00.250", 0);
+    let mut t = Timestamps::with_mode(Mode::Epoch);
+    assert_eq!(t.format(&utc, ""), "1514808000.250");
+    let mut t = Timestamps::with_mode(Mode::Utc);
+    assert_eq!(t.format(&utc, "%H:%M:%S"), "12:00:00");
+
+    assert_eq!(Mode::from_str("utc").unwrap(), Mode::Utc);
+    assert!(Mode::from_str("foo").is_err());
+}
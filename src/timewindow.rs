@@ -0,0 +1,235 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::Error;
+use record::Record;
+use regex::Regex;
+use std::cmp::Ordering;
+use time::{now, Duration, Tm};
+use utils::parse_duration;
+
+lazy_static! {
+    static ref ABSOLUTE: Regex = Regex::new(
+        r"^(?:(?:(\d{4})-)?(\d{1,2})-(\d{1,2})[ T])?(\d{1,2}):(\d{2})(?::(\d{2})(?:\.(\d{1,9}))?)?$"
+    ).unwrap();
+}
+
+/// A point in time given on the command line. Record timestamps
+/// often lack the year or the date. Only the components present on
+/// both sides are compared.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeBound {
+    year: Option<i32>,
+    date: Option<(i32, i32)>,
+    time: (i32, i32, i32, i32),
+}
+
+impl TimeBound {
+    fn from_tm(tm: &Tm) -> TimeBound {
+        TimeBound {
+            year: Some(tm.tm_year + 1900),
+            date: Some((tm.tm_mon, tm.tm_mday)),
+            time: (tm.tm_hour, tm.tm_min, tm.tm_sec, tm.tm_nsec),
+        }
+    }
+
+    /// Parse a absolute timestamp like "12:00", "03-01 12:00:01.123"
+    /// or "2017-03-01 12:00:01"
+    fn absolute(s: &str) -> Option<TimeBound> {
+        let c = ABSOLUTE.captures(s)?;
+        let num = |i: usize| c.get(i).and_then(|m| m.as_str().parse::<i32>().ok());
+        let nsec = c.get(7)
+            .and_then(|m| format!("{:0<9}", m.as_str()).parse::<i32>().ok())
+            .unwrap_or(0);
+        let date = match (num(2), num(3)) {
+            (Some(month), Some(day)) => Some((month - 1, day)),
+            _ => None,
+        };
+        Some(TimeBound {
+            year: num(1),
+            date,
+            time: (num(4)?, num(5)?, num(6).unwrap_or(0), nsec),
+        })
+    }
+
+    /// Compare the timestamp of a record with this bound
+    pub fn cmp_tm(&self, tm: &Tm) -> Ordering {
+        // The parser stores the year as is and 0 if the year is unknown
+        let year = match self.year {
+            Some(y) if tm.tm_year != 0 => tm.tm_year.cmp(&y),
+            _ => Ordering::Equal,
+        };
+        let date = self.date
+            .map(|d| (tm.tm_mon, tm.tm_mday).cmp(&d))
+            .unwrap_or(Ordering::Equal);
+        let time = (tm.tm_hour, tm.tm_min, tm.tm_sec, tm.tm_nsec).cmp(&self.time);
+        year.then(date).then(time)
+    }
+
+    /// Convert into a local time using `now` for missing components
+    fn to_tm(&self, now: &Tm) -> Tm {
+        let mut tm = *now;
+        if let Some(year) = self.year {
+            tm.tm_year = year - 1900;
+        }
+        if let Some((month, day)) = self.date {
+            tm.tm_mon = month;
+            tm.tm_mday = day;
+        }
+        tm.tm_hour = self.time.0;
+        tm.tm_min = self.time.1;
+        tm.tm_sec = self.time.2;
+        tm.tm_nsec = self.time.3;
+        tm
+    }
+
+    /// Add a duration but keep the components that are unknown unset
+    fn add(&self, now: &Tm, d: Duration) -> TimeBound {
+        let t = TimeBound::from_tm(&(self.to_tm(now) + d));
+        TimeBound {
+            year: self.year.and(t.year),
+            date: self.date.and(t.date),
+            time: t.time,
+        }
+    }
+
+    /// Seconds since epoch in the format accepted by "logcat -T"
+    pub fn epoch(&self) -> String {
+        let ts = self.to_tm(&now()).to_timespec();
+        format!("{}.{:03}", ts.sec, ts.nsec / 1_000_000)
+    }
+}
+
+/// Time window selected with --since and --until
+#[derive(Clone, Debug, Default)]
+pub struct TimeWindow {
+    since: Option<TimeBound>,
+    until: Option<TimeBound>,
+    /// Records without timestamp follow the last timestamped one
+    inside: Option<bool>,
+}
+
+impl<'a> TimeWindow {
+    pub fn new(args: &ArgMatches<'a>) -> Result<Self, Error> {
+        let now = now();
+        let since = args.value_of("since")
+            .map(|s| {
+                parse_duration(s)
+                    .map(|d| TimeBound::from_tm(&(now - d)))
+                    .or_else(|| TimeBound::absolute(s))
+                    .ok_or_else(|| format_err!("Invalid time or duration: {}", s))
+            })
+            .map_or(Ok(None), |s| s.map(Some))?;
+        // A relative until is relative to since if present
+        let until = args.value_of("until")
+            .map(|s| {
+                parse_duration(s)
+                    .map(|d| match since {
+                        Some(ref since) => since.add(&now, d),
+                        None => TimeBound::from_tm(&(now + d)),
+                    })
+                    .or_else(|| TimeBound::absolute(s))
+                    .ok_or_else(|| format_err!("Invalid time or duration: {}", s))
+            })
+            .map_or(Ok(None), |s| s.map(Some))?;
+
+        Ok(TimeWindow {
+            since,
+            until,
+            inside: None,
+        })
+    }
+
+    pub fn since(&self) -> &Option<TimeBound> {
+        &self.since
+    }
+
+    /// True if the record is later than the until bound. Bounds without
+    /// a date never pass because the time of day wraps at midnight.
+    pub fn is_passed(&self, record: &Option<Record>) -> bool {
+        match (record, &self.until) {
+            (&Some(Record { timestamp: Some(ref ts), .. }), &Some(ref until)) if until.date.is_some() => {
+                until.cmp_tm(ts) == Ordering::Greater
+            }
+            _ => false,
+        }
+    }
+
+    pub fn contains(&mut self, record: &Record) -> bool {
+        if let Some(ref ts) = record.timestamp {
+            let after_since = self.since
+                .as_ref()
+                .map(|s| s.cmp_tm(ts) != Ordering::Less)
+                .unwrap_or(true);
+            let before_until = self.until
+                .as_ref()
+                .map(|u| u.cmp_tm(ts) != Ordering::Greater)
+                .unwrap_or(true);
+            let inside = after_since && before_until;
+            self.inside = Some(inside);
+            inside
+        } else {
+            self.inside.unwrap_or_else(|| self.since.is_none())
+        }
+    }
+}
+
+#[test]
+fn parse_absolute() {
+    assert_eq!(
+        TimeBound::absolute("12:01"),
+        Some(TimeBound {
+            year: None,
+            date: None,
+            time: (12, 1, 0, 0),
+        })
+    );
+    assert_eq!(
+        TimeBound::absolute("03-01 02:19:45.207"),
+        Some(TimeBound {
+            year: None,
+            date: Some((2, 1)),
+            time: (2, 19, 45, 207_000_000),
+        })
+    );
+    assert_eq!(
+        TimeBound::absolute("2017-03-01 02:19:45"),
+        Some(TimeBound {
+            year: Some(2017),
+            date: Some((2, 1)),
+            time: (2, 19, 45, 0),
+        })
+    );
+    assert_eq!(TimeBound::absolute("12"), None);
+    assert_eq!(TimeBound::absolute("5m"), None);
+}
+
+#[test]
+fn passed() {
+    use time::strptime;
+    let record = |ts: &str| {
+        Some(Record {
+            timestamp: Some(::record::Timestamp::new(strptime(ts, "%m-%d %H:%M:%S").unwrap())),
+            ..Default::default()
+        })
+    };
+    let window = |until: &str| TimeWindow {
+        since: None,
+        until: TimeBound::absolute(until),
+        inside: None,
+    };
+
+    // Logs that cross midnight
+    let w = window("00:30");
+    assert!(!w.is_passed(&record("02-28 23:50:00")));
+    assert!(!w.is_passed(&record("03-01 00:40:00")));
+
+    let w = window("03-01 00:30");
+    assert!(!w.is_passed(&record("02-28 23:50:00")));
+    assert!(!w.is_passed(&record("03-01 00:20:00")));
+    assert!(w.is_passed(&record("03-01 00:40:00")));
+}
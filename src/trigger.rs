@@ -0,0 +1,309 @@
+// Copyright © 2017 Felix Obenhuber
+// This program is free software. It comes without any warranty, to the extent
+// permitted by applicable law. You can redistribute it and/or modify it under
+// the terms of the Do What The Fuck You Want To Public License, Version 2, as
+// published by Sam Hocevar. See the COPYING file for more details.
+
+use clap::ArgMatches;
+use failure::Error;
+use futures::future::join_all;
+use futures::sync::oneshot;
+use futures::{Async, Future};
+use handlebars::{no_escape, Handlebars};
+use profiles::Profile;
+use record::{Level, Record};
+use regex::Regex;
+use serde_json::{to_value, Value};
+use std::collections::HashMap;
+use std::io::{stderr, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Handle;
+use tokio_process::CommandExt;
+use utils::parse_duration;
+use RFuture;
+
+/// Minimum number of seconds between two runs of the same trigger
+const DEFAULT_INTERVAL: u64 = 10;
+
+/// Trigger as configured in a profile
+#[derive(Clone, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct TriggerSpec {
+    pub command: String,
+    pub interval: Option<String>,
+    pub level: Option<String>,
+    pub message: Option<String>,
+    pub tag: Option<String>,
+}
+
+struct Trigger {
+    command: String,
+    interval: Duration,
+    last: Option<Instant>,
+    level: Level,
+    message: Option<Regex>,
+    tag: Option<Regex>,
+}
+
+impl Trigger {
+    fn is_match(&self, record: &Record) -> bool {
+        record.level >= self.level
+            && self.message
+                .as_ref()
+                .map(|m| m.is_match(&record.message))
+                .unwrap_or(true)
+            && self.tag
+                .as_ref()
+                .map(|t| t.is_match(&record.tag))
+                .unwrap_or(true)
+    }
+
+    /// Check and update the rate limit
+    fn fire(&mut self, now: Instant) -> bool {
+        match self.last {
+            Some(last) if now.duration_since(last) < self.interval => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Quoted reference to the environment variable `name`. The shell
+/// expands it after parsing the command line so record text cannot
+/// inject commands.
+#[cfg(not(windows))]
+fn variable(name: &str) -> String {
+    format!("\"${}\"", name)
+}
+
+/// Quoted reference to the environment variable `name`. Delayed expansion
+/// happens after cmd parsed the command line so record text cannot
+/// inject commands.
+#[cfg(windows)]
+fn variable(name: &str) -> String {
+    format!("\"!{}!\"", name)
+}
+
+#[cfg(not(windows))]
+fn shell() -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c");
+    command
+}
+
+#[cfg(windows)]
+fn shell() -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/V:ON").arg("/C");
+    command
+}
+
+/// Runs commands if a record matches a trigger. The record fields are
+/// passed as ROGCAT_<FIELD> environment variables and can be used as
+/// template arguments in the command. Template arguments expand to the
+/// quoted environment variables.
+pub struct Triggers {
+    handle: Handle,
+    handlebars: Handlebars,
+    running: Vec<oneshot::Receiver<()>>,
+    triggers: Vec<Trigger>,
+}
+
+impl<'a> Triggers {
+    pub fn new(args: &ArgMatches<'a>, profile: &Profile, handle: Handle) -> Result<Self, Error> {
+        let duration = |d: &str| {
+            parse_duration(d)
+                .and_then(|d| d.to_std().ok())
+                .ok_or_else(|| format_err!("Invalid trigger interval: {}", d))
+        };
+        let regex = |r: &str| Regex::new(r).map_err(|_| format_err!("Invalid regex string: {}", r));
+        let interval = match args.value_of("trigger_interval") {
+            Some(i) => duration(i)?,
+            None => Duration::from_secs(DEFAULT_INTERVAL),
+        };
+
+        let mut triggers = vec![];
+        if let Some(values) = args.values_of("trigger") {
+            let values = values.collect::<Vec<&str>>();
+            for t in values.chunks(2) {
+                triggers.push(Trigger {
+                    command: t[1].to_owned(),
+                    interval,
+                    last: None,
+                    level: Level::None,
+                    message: Some(regex(t[0])?),
+                    tag: None,
+                });
+            }
+        }
+        for t in profile.trigger() {
+            triggers.push(Trigger {
+                command: t.command.clone(),
+                interval: t.interval
+                    .as_ref()
+                    .map(|i| duration(i))
+                    .unwrap_or(Ok(interval))?,
+                last: None,
+                level: t.level.as_ref().map(|l| Level::from(l.as_str())).unwrap_or_default(),
+                message: t.message.as_ref().map(|m| regex(m)).map_or(Ok(None), |m| m.map(Some))?,
+                tag: t.tag.as_ref().map(|m| regex(m)).map_or(Ok(None), |m| m.map(Some))?,
+            });
+        }
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(no_escape);
+
+        Ok(Triggers {
+            handle,
+            handlebars,
+            running: vec![],
+            triggers,
+        })
+    }
+
+    /// Environment variables passed to the command
+    fn env(record: &Record) -> Vec<(String, String)> {
+        match to_value(record) {
+            Ok(Value::Object(fields)) => fields
+                .iter()
+                .map(|(k, v)| {
+                    let v = match *v {
+                        Value::String(ref s) => s.clone(),
+                        Value::Null => String::new(),
+                        ref v => v.to_string(),
+                    };
+                    (format!("ROGCAT_{}", k.to_uppercase()), v)
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Template arguments of the fields of `record`
+    fn arguments(record: &Record) -> HashMap<String, String> {
+        Self::env(record)
+            .iter()
+            .map(|&(ref name, _)| (name["ROGCAT_".len()..].to_lowercase(), variable(name)))
+            .collect()
+    }
+
+    fn run(&mut self, command: &str, record: &Record) -> Result<(), Error> {
+        let command = self.handlebars
+            .template_render(command, &Self::arguments(record))
+            .map_err(|e| format_err!("Failed to render trigger command {}: {}", command, e))?;
+        let (tx, rx) = oneshot::channel();
+        let child = shell()
+            .arg(&command)
+            .envs(Self::env(record))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output_async(&self.handle)
+            .map(move |output| {
+                if !output.status.success() {
+                    writeln!(stderr(), "Trigger \"{}\" failed: {}", command, output.status).ok();
+                }
+            })
+            .map_err(|_| ())
+            .then(|_| tx.send(()));
+        self.handle.spawn(child);
+        self.running.push(rx);
+        Ok(())
+    }
+
+    /// Run the commands of all triggers matching `record`
+    pub fn process(&mut self, record: &Option<Record>) -> Result<(), Error> {
+        if let Some(ref record) = *record {
+            if self.triggers.is_empty() {
+                return Ok(());
+            }
+
+            // Forget about commands that terminated
+            self.running = self.running
+                .drain(..)
+                .filter_map(|mut r| match r.poll() {
+                    Ok(Async::NotReady) => Some(r),
+                    _ => None,
+                })
+                .collect();
+
+            let now = Instant::now();
+            let mut commands = vec![];
+            for t in &mut self.triggers {
+                if t.is_match(record) && t.fire(now) {
+                    commands.push(t.command.clone());
+                }
+            }
+            for c in commands {
+                self.run(&c, record)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Future that resolves once all commands started terminated
+    pub fn finish(&mut self) -> RFuture {
+        let running = self.running
+            .drain(..)
+            .map(|r| r.then(|_| Ok(())))
+            .collect::<Vec<_>>();
+        Box::new(join_all(running).map(|_| ()))
+    }
+}
+
+#[test]
+fn trigger() {
+    let record = |level: Level, tag: &str, message: &str| Record {
+        level,
+        tag: tag.to_owned(),
+        message: message.to_owned(),
+        ..Default::default()
+    };
+    let mut trigger = Trigger {
+        command: String::new(),
+        interval: Duration::from_secs(10),
+        last: None,
+        level: Level::Error,
+        message: Some(Regex::new("^FATAL").unwrap()),
+        tag: None,
+    };
+    assert!(trigger.is_match(&record(Level::Error, "AndroidRuntime", "FATAL EXCEPTION")));
+    assert!(!trigger.is_match(&record(Level::Info, "AndroidRuntime", "FATAL EXCEPTION")));
+    assert!(!trigger.is_match(&record(Level::Error, "AndroidRuntime", "Exception")));
+
+    let now = Instant::now();
+    assert!(trigger.fire(now));
+    assert!(!trigger.fire(now + Duration::from_secs(5)));
+    assert!(trigger.fire(now + Duration::from_secs(10)));
+}
+
+#[test]
+fn trigger_env() {
+    let record = Record {
+        level: Level::Error,
+        tag: "AndroidRuntime".to_owned(),
+        message: "FATAL EXCEPTION: main".to_owned(),
+        process: "123".to_owned(),
+        ..Default::default()
+    };
+    let env = Triggers::env(&record);
+    assert!(env.contains(&("ROGCAT_TAG".to_owned(), "AndroidRuntime".to_owned())));
+    assert!(env.contains(&("ROGCAT_PROCESS".to_owned(), "123".to_owned())));
+    assert!(env.contains(&("ROGCAT_LEVEL".to_owned(), "Error".to_owned())));
+    assert!(env.contains(&("ROGCAT_TIMESTAMP".to_owned(), String::new())));
+
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(no_escape);
+    let render = |t: &str| {
+        handlebars
+            .template_render(t, &Triggers::arguments(&record))
+            .unwrap()
+    };
+    assert_eq!(
+        render("echo {{message}} > {{process}}.txt"),
+        format!("echo {} > {}.txt", variable("ROGCAT_MESSAGE"), variable("ROGCAT_PROCESS"))
+    );
+    assert_eq!(render("{{level}}"), variable("ROGCAT_LEVEL"));
+}
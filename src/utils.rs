@@ -5,10 +5,14 @@
 // published by Sam Hocevar. See the COPYING file for more details.
 
 use futures::{Poll, Stream};
+use regex::Regex;
 use std::env;
 use std::io::BufRead;
+use std::str::FromStr;
 use term_size::dimensions;
+use time::Duration;
 use tokio_io::AsyncRead;
+use unicode_width::UnicodeWidthChar;
 
 pub fn terminal_width() -> Option<usize> {
     match dimensions() {
@@ -18,6 +22,91 @@ pub fn terminal_width() -> Option<usize> {
             .and_then(|e| e.parse::<usize>().ok()),
     }}
 
+lazy_static! {
+    // CSI, OSC and two byte escape sequences
+    static ref ANSI: Regex = Regex::new(r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(?:\x07|\x1b\\)?|[@-Z\\-_])").unwrap();
+    static ref DURATION: Regex = Regex::new(r"^(?:\d+(?:ms|s|m|h|d))+$").unwrap();
+    static ref DURATION_PART: Regex = Regex::new(r"(\d+)(ms|s|m|h|d)").unwrap();
+    static ref SIZE: Regex = Regex::new(r"^(\d+)([kMG])$").unwrap();
+}
+
+/// Parse a plain number or a number with a k, M or G suffix
+pub fn parse_size(s: &str) -> Option<u64> {
+    SIZE.captures(s)
+        .and_then(|caps| {
+            let size = u64::from_str(&caps[1]).ok()?;
+            match &caps[2] {
+                "k" => Some(1_000 * size),
+                "M" => Some(1_000_000 * size),
+                _ => Some(1_000_000_000 * size),
+            }
+        })
+        .or_else(|| u64::from_str(s).ok())
+}
+
+/// Parse a duration like "90s", "5m" or "1h30m"
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    if !DURATION.is_match(s) {
+        return None;
+    }
+    DURATION_PART
+        .captures_iter(s)
+        .map(|c| {
+            let n = c[1].parse::<i64>().ok()?;
+            Some(match &c[2] {
+                "ms" => Duration::milliseconds(n),
+                "s" => Duration::seconds(n),
+                "m" => Duration::minutes(n),
+                "h" => Duration::hours(n),
+                _ => Duration::days(n),
+            })
+        })
+        .fold(Some(Duration::zero()), |a, d| Some(a? + d?))
+}
+
+/// Strip escape sequences and make control characters visible
+/// in caret notation. Tabs are expanded to four spaces.
+pub fn sanitize(s: &str) -> String {
+    if !s.chars().any(|c| c.is_control()) {
+        return s.to_owned();
+    }
+    let mut result = String::with_capacity(s.len());
+    for c in ANSI.replace_all(s, "").chars() {
+        match c {
+            '\t' => result.push_str("    "),
+            '\x7f' => result.push_str("^?"),
+            c if c < ' ' => {
+                result.push('^');
+                result.push((c as u8 + b'@') as char);
+            }
+            c if c.is_control() => result.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Split `s` into chunks of at most `width` columns. Chars wider than
+/// `width` get a chunk of their own.
+pub fn wrap(s: &str, width: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut columns = 0;
+    for (i, c) in s.char_indices() {
+        let w = c.width().unwrap_or(0);
+        if columns + w > width && i > start {
+            chunks.push(&s[start..i]);
+            start = i;
+            columns = 0;
+        }
+        columns += w;
+    }
+    if start < s.len() || chunks.is_empty() {
+        chunks.push(&s[start..]);
+    }
+    chunks
+}
+
 pub struct LossyLines<A> {
     io: A,
     buffer: Vec<u8>,
@@ -53,4 +142,42 @@ where
             Ok(Some(line).into())
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn duration() {
+    assert_eq!(parse_duration("90s"), Some(Duration::seconds(90)));
+    assert_eq!(parse_duration("1h30m"), Some(Duration::minutes(90)));
+    assert_eq!(parse_duration("250ms"), Some(Duration::milliseconds(250)));
+    assert_eq!(parse_duration("1d"), Some(Duration::days(1)));
+    assert_eq!(parse_duration("12:00"), None);
+    assert_eq!(parse_duration("5x"), None);
+}
+
+#[test]
+fn size() {
+    assert_eq!(parse_size("100"), Some(100));
+    assert_eq!(parse_size("2k"), Some(2_000));
+    assert_eq!(parse_size("10M"), Some(10_000_000));
+    assert_eq!(parse_size("1G"), Some(1_000_000_000));
+    assert_eq!(parse_size("1T"), None);
+    assert_eq!(parse_size("k"), None);
+}
+
+#[test]
+fn sanitize_control_characters() {
+    assert_eq!(sanitize("plain"), "plain");
+    assert_eq!(sanitize("\x1b[31mred\x1b[0m"), "red");
+    assert_eq!(sanitize("\x1b]0;title\x07text"), "text");
+    assert_eq!(sanitize("a\tb\rc\x00"), "a    b^Mc^@");
+    assert_eq!(sanitize("\u{9b}"), "\\u{9b}");
+}
+
+#[test]
+fn wrap_width() {
+    assert_eq!(wrap("abcdef", 4), vec!["abcd", "ef"]);
+    assert_eq!(wrap("日本語です", 4), vec!["日本", "語で", "す"]);
+    assert_eq!(wrap("a日本", 2), vec!["a", "日", "本"]);
+    assert_eq!(wrap("日", 1), vec!["日"]);
+    assert_eq!(wrap("", 4), vec![""]);
+}